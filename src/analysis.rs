@@ -0,0 +1,439 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+//! Heuristics which flag suspicious executions.
+//!
+//! An `Analyzer` runs a set of `Rule`s over a parsed `Prefetch` and returns
+//! the `Finding`s, each with a severity and an explanation. The rules only
+//! use the executable path, see `Prefetch::executable_path`, the metric
+//! filenames and the volumes: a rule whose data is missing or could not be
+//! parsed is skipped.
+//!
+//! # Example
+//!
+//! ```
+//! use libprefetch::Prefetch;
+//! use libprefetch::analysis::{Analyzer, Rule, Severity};
+//!
+//! let prefetch =
+//!     Prefetch::from_path("assets/CMD.EXE-0BD30981.pf").unwrap();
+//!
+//! let findings = Analyzer::new().analyze(&prefetch);
+//! assert_eq!(Rule::UserWritableLibrary, findings[0].rule());
+//! assert_eq!(Severity::High, findings[0].severity());
+//! for finding in &findings {
+//!   println!("{}", finding);
+//! }
+//!
+//! // Only some rules
+//! let analyzer = Analyzer::new()
+//!   .rules(vec![Rule::SuspiciousDirectory, Rule::Masquerading]);
+//! assert!(analyzer.analyze(&prefetch).is_empty());
+//! ```
+
+use std;
+
+/// Severity of a finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+
+  /// Worth a look in context.
+  Low,
+
+  /// Unusual for a legitimate program.
+  Medium,
+
+  /// Common with malware.
+  High
+}
+
+impl std::fmt::Display for Severity {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match *self {
+      Severity::Low => write!(f, "low"),
+      Severity::Medium => write!(f, "medium"),
+      Severity::High => write!(f, "high")
+    }
+  }
+}
+
+/// The heuristics an `Analyzer` can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rule {
+
+  /// The executable ran from a Temp, Downloads, AppData or ProgramData
+  /// folder.
+  SuspiciousDirectory,
+
+  /// The executable ran from another volume than the one of Windows.
+  NonSystemVolume,
+
+  /// The executable has the name of a system binary outside of its usual
+  /// directory, or a name close to it.
+  Masquerading,
+
+  /// A library was loaded from a folder writable by the users.
+  UserWritableLibrary
+}
+
+impl Rule {
+
+  /// Every rule, in the order they run.
+  pub const ALL: [Rule; 4] = [Rule::SuspiciousDirectory, Rule::NonSystemVolume,
+    Rule::Masquerading, Rule::UserWritableLibrary];
+}
+
+impl std::fmt::Display for Rule {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match *self {
+      Rule::SuspiciousDirectory => write!(f, "suspicious directory"),
+      Rule::NonSystemVolume => write!(f, "non-system volume"),
+      Rule::Masquerading => write!(f, "masquerading"),
+      Rule::UserWritableLibrary => write!(f, "user-writable library")
+    }
+  }
+}
+
+/// A suspicious trait of an execution.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Finding {
+  rule: Rule,
+  severity: Severity,
+  path: std::string::String,
+  explanation: std::string::String
+}
+
+impl Finding {
+
+  /// Returns the rule which raised the finding.
+  pub fn rule(&self) -> Rule {
+    self.rule
+  }
+
+  /// Returns the severity.
+  pub fn severity(&self) -> Severity {
+    self.severity
+  }
+
+  /// Returns the path of the file the finding is about.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Returns a sentence which explains the finding.
+  pub fn explanation(&self) -> &str {
+    &self.explanation
+  }
+}
+
+impl std::fmt::Display for Finding {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "[{}] {}: {}", self.severity, self.rule, self.explanation)
+  }
+}
+
+/// System binaries which malware often imitates, with their directory.
+const SYSTEM_BINARIES: &[(&str, &str)] = &[
+  ("CONHOST.EXE", r"\WINDOWS\SYSTEM32"),
+  ("CSRSS.EXE", r"\WINDOWS\SYSTEM32"),
+  ("DLLHOST.EXE", r"\WINDOWS\SYSTEM32"),
+  ("EXPLORER.EXE", r"\WINDOWS"),
+  ("LSASS.EXE", r"\WINDOWS\SYSTEM32"),
+  ("LSM.EXE", r"\WINDOWS\SYSTEM32"),
+  ("RUNDLL32.EXE", r"\WINDOWS\SYSTEM32"),
+  ("SERVICES.EXE", r"\WINDOWS\SYSTEM32"),
+  ("SMSS.EXE", r"\WINDOWS\SYSTEM32"),
+  ("SPOOLSV.EXE", r"\WINDOWS\SYSTEM32"),
+  ("SVCHOST.EXE", r"\WINDOWS\SYSTEM32"),
+  ("TASKHOST.EXE", r"\WINDOWS\SYSTEM32"),
+  ("TASKHOSTW.EXE", r"\WINDOWS\SYSTEM32"),
+  ("WININIT.EXE", r"\WINDOWS\SYSTEM32"),
+  ("WINLOGON.EXE", r"\WINDOWS\SYSTEM32")
+];
+
+/// Extensions of the files loaded as code.
+const LIBRARY_EXTENSIONS: &[&str] = &[".DLL", ".OCX", ".CPL", ".SYS"];
+
+/// Runs a configurable set of rules over Prefetch files.
+#[derive(Debug, Clone)]
+pub struct Analyzer {
+  rules: std::vec::Vec<Rule>
+}
+
+impl Default for Analyzer {
+
+  fn default() -> Analyzer {
+    Analyzer {
+      rules: Rule::ALL.to_vec()
+    }
+  }
+}
+
+impl Analyzer {
+
+  /// Constructs an analyzer which runs every rule.
+  pub fn new() -> Analyzer {
+    Analyzer::default()
+  }
+
+  /// Sets the rules to run, in this order.
+  pub fn rules(mut self, rules: std::vec::Vec<Rule>) -> Analyzer {
+    self.rules = rules;
+    self
+  }
+
+  /// Removes `rule` from the rules to run.
+  pub fn without(mut self, rule: Rule) -> Analyzer {
+    self.rules.retain(|&r| r != rule);
+    self
+  }
+
+  /// Runs the rules over `prefetch`, and returns the findings in the order of
+  /// the rules.
+  pub fn analyze(&self, prefetch: &super::Prefetch) -> std::vec::Vec<Finding> {
+    let context = Context::new(prefetch);
+    let mut findings = std::vec::Vec::new();
+    for rule in &self.rules {
+      match *rule {
+        Rule::SuspiciousDirectory => context.suspicious_directory(&mut findings),
+        Rule::NonSystemVolume => context.non_system_volume(&mut findings),
+        Rule::Masquerading => context.masquerading(&mut findings),
+        Rule::UserWritableLibrary => context.user_writable_library(&mut findings)
+      }
+    }
+    findings
+  }
+}
+
+/// A path split after its volume.
+struct Located {
+  path: std::string::String,
+  volume: usize,
+  // Upper-cased, from the root of the volume.
+  rest: std::string::String
+}
+
+impl Located {
+
+  fn directories(&self) -> std::vec::Vec<&str> {
+    let mut names: std::vec::Vec<&str> = self.rest.split('\\')
+      .filter(|n| !n.is_empty())
+      .collect();
+    names.pop();
+    names
+  }
+
+  fn name(&self) -> &str {
+    self.rest.rsplit('\\').next().unwrap_or("")
+  }
+
+  fn directory(&self) -> &str {
+    &self.rest[.. self.rest.rfind('\\').unwrap_or(0)]
+  }
+}
+
+/// The data the rules share.
+struct Context<'p> {
+  prefetch: &'p super::Prefetch,
+  volumes: std::vec::Vec<&'p super::volume::VolumeEntry>,
+  executable: Option<Located>
+}
+
+impl<'p> Context<'p> {
+
+  fn new(prefetch: &'p super::Prefetch) -> Context<'p> {
+    let volumes = match prefetch.volumes() {
+      Ok(volumes) => volumes.collect(),
+      Err(_) => std::vec::Vec::new()
+    };
+    let mut context = Context {
+      prefetch,
+      volumes,
+      executable: None
+    };
+    context.executable = prefetch.executable_path()
+      .and_then(|e| context.locate(e.path()));
+    context
+  }
+
+  fn locate(&self, path: &str) -> Option<Located> {
+    self.volumes.iter().enumerate().find_map(|(i, v)| {
+      let normalized = v.normalize_path(path)?;
+      Some(Located {
+        path: path.to_string(),
+        volume: i,
+        rest: normalized[v.volume_id().to_string().len() ..].to_string()
+      })
+    })
+  }
+
+  // The volume of Windows holds the NTDLL.DLL every process loads.
+  fn system_volume(&self) -> Option<usize> {
+    self.prefetch.metrics().ok()?
+      .filter_map(|m| self.locate(m.filename()))
+      .find(|l| l.rest == r"\WINDOWS\SYSTEM32\NTDLL.DLL"
+        || l.rest == r"\WINDOWS\SYSWOW64\NTDLL.DLL")
+      .map(|l| l.volume)
+  }
+
+  fn suspicious_directory(&self, findings: &mut std::vec::Vec<Finding>) {
+    let executable = match self.executable {
+      Some(ref e) => e,
+      None => return
+    };
+    let directories = executable.directories();
+    let found = |names: &[&str]| directories.iter().any(|d| names.contains(d));
+    let (severity, folder) = if found(&["TEMP", "TMP"]) {
+      (Severity::High, "a temporary folder")
+    } else if found(&["DOWNLOADS"]) {
+      (Severity::High, "a Downloads folder")
+    } else if found(&["APPDATA", "APPLICATION DATA", "LOCAL SETTINGS"]) {
+      (Severity::Medium, "an AppData folder")
+    } else if directories.first() == Some(&"PROGRAMDATA") {
+      (Severity::Medium, "the ProgramData folder")
+    } else {
+      return;
+    };
+    findings.push(Finding {
+      rule: Rule::SuspiciousDirectory,
+      severity,
+      path: executable.path.clone(),
+      explanation: format!("{} ran from {}", executable.name(), folder)
+    });
+  }
+
+  fn non_system_volume(&self, findings: &mut std::vec::Vec<Finding>) {
+    let (executable, system) = match (self.executable.as_ref(), self.system_volume()) {
+      (Some(e), Some(system)) => (e, system),
+      _ => return
+    };
+    if executable.volume != system {
+      findings.push(Finding {
+        rule: Rule::NonSystemVolume,
+        severity: Severity::Medium,
+        path: executable.path.clone(),
+        explanation: format!("{} ran from {}, Windows is on {}",
+          executable.name(), self.volumes[executable.volume].device_path(),
+          self.volumes[system].device_path())
+      });
+    }
+  }
+
+  fn masquerading(&self, findings: &mut std::vec::Vec<Finding>) {
+    let (name, path) = match self.executable {
+      Some(ref e) => (e.name().to_string(), e.path.clone()),
+      None => (self.prefetch.name().to_ascii_uppercase(),
+        self.prefetch.name().to_string())
+    };
+    // A system binary is not an imitation of another one with a close name,
+    // such as TASKHOST.EXE and TASKHOSTW.EXE.
+    let system = SYSTEM_BINARIES.iter().any(|&(binary, _)| name == binary);
+    for &(binary, directory) in SYSTEM_BINARIES {
+      let explanation = if name == binary {
+        let expected = match self.executable {
+          Some(ref e) => e.directory() == directory
+            || e.directory() == directory.replace("SYSTEM32", "SYSWOW64"),
+          None => true
+        };
+        if expected {
+          continue;
+        }
+        format!("{} ran outside of {}", binary, directory)
+      } else if !system && distance(&fold(&name), &fold(binary)) <= 1 {
+        format!("{} imitates {}", name, binary)
+      } else {
+        continue;
+      };
+      findings.push(Finding {
+        rule: Rule::Masquerading,
+        severity: Severity::High,
+        path,
+        explanation
+      });
+      return;
+    }
+  }
+
+  fn user_writable_library(&self, findings: &mut std::vec::Vec<Finding>) {
+    let metrics = match self.prefetch.metrics() {
+      Ok(metrics) => metrics,
+      Err(_) => return
+    };
+    for located in metrics.filter_map(|m| self.locate(m.filename())) {
+      if !LIBRARY_EXTENSIONS.iter().any(|e| located.rest.ends_with(e)) {
+        continue;
+      }
+      let directories = located.directories();
+      let temporary = directories.iter().any(|d| *d == "TEMP" || *d == "TMP");
+      let writable = match directories.first() {
+        Some(&"USERS") | Some(&"DOCUMENTS AND SETTINGS") | Some(&"PROGRAMDATA")
+          => true,
+        _ => temporary
+      };
+      if writable {
+        findings.push(Finding {
+          rule: Rule::UserWritableLibrary,
+          severity: match temporary {
+            true => Severity::High,
+            false => Severity::Medium
+          },
+          explanation: format!("{} was loaded from a user-writable folder",
+            located.name()),
+          path: located.path
+        });
+      }
+    }
+  }
+}
+
+// Maps the characters which look alike, such as `0` and `O`.
+fn fold(name: &str) -> std::vec::Vec<u8> {
+  name.bytes()
+    .map(|b| match b {
+      b'0' => b'O',
+      b'1' | b'L' => b'I',
+      b'5' => b'S',
+      b => b
+    })
+    .collect()
+}
+
+// Edit distance where swapping two neighbours counts as one edit.
+fn distance(a: &[u8], b: &[u8]) -> usize {
+  let mut rows = vec![(0 ..= b.len()).collect::<std::vec::Vec<usize>>()];
+  for i in 1 ..= a.len() {
+    let mut row = vec![i; b.len() + 1];
+    for j in 1 ..= b.len() {
+      let cost = (a[i - 1] != b[j - 1]) as usize;
+      row[j] = (rows[i - 1][j] + 1)
+        .min(row[j - 1] + 1)
+        .min(rows[i - 1][j - 1] + cost);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+      }
+    }
+    rows.push(row);
+  }
+  rows[a.len()][b.len()]
+}
@@ -0,0 +1,704 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+//! LZXPRESS Huffman, as described in [MS-XCA] section 2.1 and 2.2.
+//!
+//! Windows 10 compresses its prefetch files with this algorithm, inside a
+//! `MAM` container.
+
+use std;
+use super::error::Section;
+use super::util::read;
+
+/// Signature of a compressed prefetch file, followed by a format byte.
+const MAM_SIGNATURE: &[u8] = b"MAM";
+
+/// Low nibble of the format byte: LZXPRESS Huffman.
+const MAM_FORMAT_HUFFMAN: u8 = 0x04;
+
+/// High bit of the format byte: a CRC32 checksum follows the uncompressed
+/// size.
+const MAM_FLAG_CHECKSUM: u8 = 0x80;
+
+/// The data is split in blocks of 64 KiB of uncompressed data.
+const BLOCK_SIZE: usize = 65536;
+
+/// The encoder reserves the 16 bits words ahead of the bits it writes, up to
+/// two of them may end the stream unread.
+const STREAM_PADDING: usize = 4;
+
+/// Each block starts with the bit lengths of the 512 symbols, 4 bits each.
+const TABLE_SIZE: usize = 256;
+
+const SYMBOL_COUNT: usize = 512;
+
+/// Codes are at most 15 bits long.
+const MAX_CODE_LENGTH: usize = 15;
+
+/// Reads the bit stream of a block, 16 bits at a time.
+struct BitReader<'a> {
+  input: &'a [u8],
+  position: usize,
+  next_bits: u32,
+  extra_bit_count: i32,
+  // Whether a read went past the end of the input.
+  exhausted: bool
+}
+
+impl<'a> BitReader<'a> {
+
+  fn new(input: &'a [u8], position: usize) -> BitReader<'a> {
+    BitReader {
+      input,
+      position,
+      next_bits: 0,
+      extra_bit_count: 16,
+      exhausted: false
+    }
+  }
+
+  /// Loads the first 32 bits.
+  fn start(&mut self) -> super::Result<()> {
+    self.next_bits = (self.read_u16()? as u32) << 16;
+    self.next_bits |= self.read_u16()? as u32;
+    Ok(())
+  }
+
+  fn read_u8(&mut self) -> super::Result<u8> {
+    let b = match self.input.get(self.position) {
+      Some(b) => *b,
+      None => {
+        self.exhausted = true;
+        return Err(super::error::Error::CorruptedCompressedData);
+      }
+    };
+    self.position += 1;
+    Ok(b)
+  }
+
+  fn read_u16(&mut self) -> super::Result<u16> {
+    let lo = self.read_u8()? as u16;
+    let hi = self.read_u8()? as u16;
+    Ok(lo | hi << 8)
+  }
+
+  fn read_u32(&mut self) -> super::Result<u32> {
+    let lo = self.read_u16()? as u32;
+    let hi = self.read_u16()? as u32;
+    Ok(lo | hi << 16)
+  }
+
+  /// Returns the next `n` bits without consuming them.
+  fn peek(&self, n: usize) -> usize {
+    if n == 0 {
+      0
+    } else {
+      (self.next_bits >> (32 - n)) as usize
+    }
+  }
+
+  fn consume(&mut self, n: usize) -> super::Result<()> {
+    self.next_bits <<= n;
+    self.extra_bit_count -= n as i32;
+    if self.extra_bit_count < 0 {
+      self.next_bits |= (self.read_u16()? as u32) << (-self.extra_bit_count);
+      self.extra_bit_count += 16;
+    }
+    Ok(())
+  }
+}
+
+/// Builds the decoding table of a block: for every 15 bits value, the
+/// symbol whose code is a prefix of it.
+fn decoding_table(table: &[u8]) -> super::Result<(std::vec::Vec<u16>,
+    [u8; SYMBOL_COUNT])> {
+  let mut lengths = [0u8; SYMBOL_COUNT];
+  for (symbol, length) in lengths.iter_mut().enumerate() {
+    *length = (table[symbol / 2] >> (4 * (symbol % 2))) & 0xf;
+  }
+
+  let mut decoding = vec![0u16; 1 << MAX_CODE_LENGTH];
+  let mut entry = 0usize;
+  for bit_length in 1 ..= MAX_CODE_LENGTH {
+    for (symbol, length) in lengths.iter().enumerate() {
+      if *length as usize == bit_length {
+        let count = 1 << (MAX_CODE_LENGTH - bit_length);
+        if entry + count > decoding.len() {
+          return Err(super::error::Error::CorruptedCompressedData);
+        }
+        for d in &mut decoding[entry .. entry + count] {
+          *d = symbol as u16;
+        }
+        entry += count;
+      }
+    }
+  }
+  if entry != decoding.len() {
+    return Err(super::error::Error::CorruptedCompressedData);
+  }
+
+  Ok((decoding, lengths))
+}
+
+/// Decompresses a LZXPRESS Huffman stream into `uncompressed_size` bytes.
+pub(crate) fn decompress(input: &[u8], uncompressed_size: usize)
+    -> super::Result<std::vec::Vec<u8>> {
+  // The size comes from the file, do not trust it to reserve memory.
+  let mut output = std::vec::Vec::<u8>::with_capacity(
+    std::cmp::min(uncompressed_size, input.len().saturating_mul(16)));
+  let mut position = 0usize;
+  match decompress_blocks(input, uncompressed_size, &mut output,
+      &mut position)? {
+    true => Ok(output),
+    false => Err(super::error::Error::CorruptedCompressedData)
+  }
+}
+
+/// Carries on decompressing the LZXPRESS Huffman stream at the start of
+/// `input` into `uncompressed_size` bytes, from the block at `position` of
+/// which `output` holds the data before it. Returns whether the stream is
+/// complete: otherwise `input` ends before it does, and `output` and
+/// `position` are left at the start of the last block.
+fn decompress_blocks(input: &[u8], uncompressed_size: usize,
+    output: &mut std::vec::Vec<u8>, position: &mut usize)
+    -> super::Result<bool> {
+  while output.len() < uncompressed_size {
+    let table = match input.get(*position .. *position + TABLE_SIZE) {
+      Some(table) => table,
+      None => return Ok(false)
+    };
+    let (decoding, lengths) = decoding_table(table)?;
+    let mut bits = BitReader::new(input, *position + TABLE_SIZE);
+    let block_start = output.len();
+    let block_end = std::cmp::min(block_start + BLOCK_SIZE, uncompressed_size);
+
+    let block = bits.start()
+      .and_then(|_| decode_block(&mut bits, &decoding, &lengths, output,
+        block_end, uncompressed_size));
+    if let Err(e) = block {
+      output.truncate(block_start);
+      return match bits.exhausted {
+        true => Ok(false),
+        false => Err(e)
+      };
+    }
+
+    *position = bits.position;
+  }
+
+  if output.len() != uncompressed_size {
+    return Err(super::error::Error::UncompressedSizeMismatch(
+        uncompressed_size, output.len()));
+  }
+
+  Ok(true)
+}
+
+/// Decodes the symbols of a block until `output` holds `end` bytes. A match
+/// may run past `end`, not past `size`, the uncompressed size of the stream.
+fn decode_block(bits: &mut BitReader, decoding: &[u16],
+    lengths: &[u8; SYMBOL_COUNT], output: &mut std::vec::Vec<u8>, end: usize,
+    size: usize) -> super::Result<()> {
+  while output.len() < end {
+    let symbol = decoding[bits.peek(MAX_CODE_LENGTH)] as usize;
+    bits.consume(lengths[symbol] as usize)?;
+
+    if symbol < 256 {
+      output.push(symbol as u8);
+      continue;
+    }
+
+    let symbol = symbol - 256;
+    let mut length = symbol & 0xf;
+    let offset_bits = symbol >> 4;
+    if length == 15 {
+      length = bits.read_u8()? as usize;
+      if length == 255 {
+        length = bits.read_u16()? as usize;
+        // A zero length is followed by the length on 32 bits.
+        if length == 0 {
+          length = bits.read_u32()? as usize;
+        }
+        if length < 15 {
+          return Err(super::error::Error::CorruptedCompressedData);
+        }
+        length -= 15;
+      }
+      length += 15;
+    }
+    length += 3;
+
+    let offset = bits.peek(offset_bits) + (1 << offset_bits);
+    bits.consume(offset_bits)?;
+    if offset > output.len() || length > size - output.len() {
+      return Err(super::error::Error::CorruptedCompressedData);
+    }
+    // The match may overlap the bytes it produces.
+    let start = output.len() - offset;
+    for i in 0 .. length {
+      let b = output[start + i];
+      output.push(b);
+    }
+  }
+  Ok(())
+}
+
+/// Matches are searched among the last occurrences of their first 3 bytes.
+const MAX_CHAIN_LENGTH: usize = 32;
+
+/// Offsets are stored with at most 15 extra bits.
+const MAX_OFFSET: usize = 65535;
+
+/// The symbol ending the stream, a match of 3 bytes at offset 1.
+const END_OF_STREAM: usize = 256;
+
+/// Writes the bit stream of a block, mirroring `BitReader`.
+///
+/// The bits are written in 16 bits words, raw bytes are written between
+/// them: a word is only reserved when its first bit is written, and two
+/// words are always reserved ahead, as the reader loads them in advance.
+struct BitWriter<'a> {
+  output: &'a mut std::vec::Vec<u8>,
+  slots: [usize; 2],
+  word: u16,
+  bit_count: usize
+}
+
+impl<'a> BitWriter<'a> {
+
+  fn new(output: &'a mut std::vec::Vec<u8>) -> BitWriter<'a> {
+    let start = output.len();
+    output.extend(&[0u8; 4]);
+    BitWriter {
+      output,
+      slots: [start, start + 2],
+      word: 0,
+      bit_count: 0
+    }
+  }
+
+  fn write_bits(&mut self, value: usize, n: usize) {
+    for i in (0 .. n).rev() {
+      if self.bit_count == 16 {
+        self.flush();
+        self.slots = [self.slots[1], self.output.len()];
+        self.output.extend(&[0u8; 2]);
+        self.word = 0;
+        self.bit_count = 0;
+      }
+      self.word |= (((value >> i) & 1) as u16) << (15 - self.bit_count);
+      self.bit_count += 1;
+    }
+  }
+
+  fn write_u8(&mut self, b: u8) {
+    self.output.push(b);
+  }
+
+  fn write_u16(&mut self, v: u16) {
+    self.output.extend(&v.to_le_bytes());
+  }
+
+  fn flush(&mut self) {
+    self.output[self.slots[0] .. self.slots[0] + 2]
+      .copy_from_slice(&self.word.to_le_bytes());
+  }
+}
+
+/// A literal byte, or a match of `length` bytes at `offset`.
+enum Token {
+  Literal(u8),
+  Match(usize, usize)
+}
+
+impl Token {
+
+  fn symbol(&self) -> usize {
+    match *self {
+      Token::Literal(b) => b as usize,
+      Token::Match(length, offset) => 256 + (offset_bits(offset) << 4)
+        + std::cmp::min(length - 3, 15)
+    }
+  }
+}
+
+fn offset_bits(offset: usize) -> usize {
+  (usize::BITS - 1 - offset.leading_zeros()) as usize
+}
+
+/// Returns the bit lengths of a Huffman code for `frequencies`, at most
+/// `MAX_CODE_LENGTH` bits long.
+///
+/// The code is complete, as the decoder requires, so at least two symbols get
+/// a code.
+fn code_lengths(frequencies: &[usize; SYMBOL_COUNT]) -> [u8; SYMBOL_COUNT] {
+  let mut frequencies = *frequencies;
+  for symbol in 0 .. 2 {
+    if frequencies.iter().filter(|f| **f != 0).count() < 2
+        && frequencies[symbol] == 0 {
+      frequencies[symbol] = 1;
+    }
+  }
+
+  loop {
+    let mut heap = std::collections::BinaryHeap::new();
+    // Leaves are the symbols, inner nodes are appended after them.
+    let mut parents = vec![0usize; 2 * SYMBOL_COUNT];
+    for (symbol, f) in frequencies.iter().enumerate() {
+      if *f != 0 {
+        heap.push(std::cmp::Reverse((*f, symbol)));
+      }
+    }
+    let mut next = SYMBOL_COUNT;
+    while heap.len() > 1 {
+      let std::cmp::Reverse((f1, n1)) = heap.pop().unwrap();
+      let std::cmp::Reverse((f2, n2)) = heap.pop().unwrap();
+      parents[n1] = next;
+      parents[n2] = next;
+      heap.push(std::cmp::Reverse((f1 + f2, next)));
+      next += 1;
+    }
+    let root = next - 1;
+
+    let mut lengths = [0u8; SYMBOL_COUNT];
+    let mut max = 0;
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+      if frequencies[symbol] != 0 {
+        let mut n = symbol;
+        let mut depth = 0;
+        while n != root {
+          n = parents[n];
+          depth += 1;
+        }
+        *length = depth as u8;
+        max = std::cmp::max(max, depth);
+      }
+    }
+    if max <= MAX_CODE_LENGTH {
+      return lengths;
+    }
+    // Flatten the distribution until the code is short enough.
+    for f in frequencies.iter_mut().filter(|f| **f != 0) {
+      *f = (*f >> 1) | 1;
+    }
+  }
+}
+
+/// Returns the canonical codes of `lengths`, as assigned by
+/// `decoding_table`.
+fn canonical_codes(lengths: &[u8; SYMBOL_COUNT]) -> [u16; SYMBOL_COUNT] {
+  let mut codes = [0u16; SYMBOL_COUNT];
+  let mut entry = 0usize;
+  for bit_length in 1 ..= MAX_CODE_LENGTH {
+    for (symbol, length) in lengths.iter().enumerate() {
+      if *length as usize == bit_length {
+        codes[symbol] = (entry >> (MAX_CODE_LENGTH - bit_length)) as u16;
+        entry += 1 << (MAX_CODE_LENGTH - bit_length);
+      }
+    }
+  }
+  codes
+}
+
+/// Finds the LZ77 tokens of `input[start .. end]`, matches referencing the
+/// previous bytes of `input`.
+fn tokens(input: &[u8], start: usize, end: usize,
+    heads: &mut std::collections::HashMap<[u8; 3], std::vec::Vec<usize>>)
+    -> std::vec::Vec<Token> {
+  let mut tokens = std::vec::Vec::new();
+  let mut position = start;
+  while position < end {
+    let mut best = (0, 0);
+    if position + 3 <= end {
+      let key = [input[position], input[position + 1], input[position + 2]];
+      if let Some(candidates) = heads.get(&key) {
+        for candidate in candidates.iter().rev().take(MAX_CHAIN_LENGTH) {
+          let offset = position - candidate;
+          if offset > MAX_OFFSET {
+            break;
+          }
+          let length = input[position .. end].iter()
+            .zip(&input[*candidate ..])
+            .take_while(|(a, b)| a == b)
+            .count();
+          if length > best.0 {
+            best = (length, offset);
+          }
+        }
+      }
+    }
+
+    let length = if best.0 >= 3 {
+      tokens.push(Token::Match(best.0, best.1));
+      best.0
+    } else {
+      tokens.push(Token::Literal(input[position]));
+      1
+    };
+    for p in position .. position + length {
+      if p + 3 <= input.len() {
+        heads.entry([input[p], input[p + 1], input[p + 2]])
+          .or_default().push(p);
+      }
+    }
+    position += length;
+  }
+  tokens
+}
+
+/// Compresses `input` into a LZXPRESS Huffman stream.
+pub(crate) fn compress(input: &[u8]) -> std::vec::Vec<u8> {
+  let mut output = std::vec::Vec::new();
+  let mut heads = std::collections::HashMap::new();
+  let mut start = 0;
+
+  loop {
+    let end = std::cmp::min(start + BLOCK_SIZE, input.len());
+    let tokens = tokens(input, start, end, &mut heads);
+    let last = end == input.len();
+
+    let mut frequencies = [0usize; SYMBOL_COUNT];
+    for token in &tokens {
+      frequencies[token.symbol()] += 1;
+    }
+    if last {
+      frequencies[END_OF_STREAM] += 1;
+    }
+    let lengths = code_lengths(&frequencies);
+    let codes = canonical_codes(&lengths);
+    for pair in lengths.chunks(2) {
+      output.push(pair[0] | pair[1] << 4);
+    }
+
+    let mut bits = BitWriter::new(&mut output);
+    for token in &tokens {
+      let symbol = token.symbol();
+      bits.write_bits(codes[symbol] as usize, lengths[symbol] as usize);
+      if let Token::Match(length, offset) = *token {
+        let length = length - 3;
+        if length >= 15 {
+          if length - 15 < 255 {
+            bits.write_u8((length - 15) as u8);
+          } else {
+            bits.write_u8(255);
+            bits.write_u16(length as u16);
+          }
+        }
+        let n = offset_bits(offset);
+        bits.write_bits(offset - (1 << n), n);
+      }
+    }
+    if last {
+      bits.write_bits(codes[END_OF_STREAM] as usize,
+        lengths[END_OF_STREAM] as usize);
+    }
+    bits.flush();
+
+    if last {
+      return output;
+    }
+    start = end;
+  }
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+  let mut table = [0u32; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut c = i as u32;
+    let mut k = 0;
+    while k < 8 {
+      c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+      k += 1;
+    }
+    table[i] = c;
+    i += 1;
+  }
+  table
+}
+
+/// Updates a CRC32 (IEEE 802.3, as computed by `RtlComputeCrc32`).
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+  let mut crc = !crc;
+  for b in data {
+    crc = CRC32_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8);
+  }
+  !crc
+}
+
+/// Returns whether `content` is a `MAM` compressed file.
+pub(crate) fn is_compressed(content: &[u8]) -> bool {
+  content.starts_with(MAM_SIGNATURE)
+}
+
+/// Returns whether a `MAM` compressed file has a checksum.
+pub(crate) fn has_checksum(content: &[u8]) -> bool {
+  content.get(3).is_some_and(|f| f & MAM_FLAG_CHECKSUM != 0)
+}
+
+/// Decompresses a `MAM` file, verifying its checksum if it has one.
+///
+/// The header is the signature, the format byte and the uncompressed size.
+/// With `MAM_FLAG_CHECKSUM`, it is followed by a CRC32 computed over the whole
+/// file, the checksum field being zeroed.
+pub fn decompress_mam(content: &[u8])
+    -> super::Result<std::vec::Vec<u8>> {
+  let (uncompressed_size, start) = mam_header(content)?;
+  if has_checksum(content) {
+    let (expected, computed) = checksums(content)?;
+    if expected != computed {
+      return Err(super::error::Error::ChecksumMismatch(expected, computed));
+    }
+  }
+
+  decompress(&content[start ..], uncompressed_size)
+}
+
+/// Decompresses a `MAM` file as its data is read, when its length is not
+/// known: the compressed size is not stored.
+pub(crate) struct MamDecoder {
+  output: std::vec::Vec<u8>,
+  // Offset in the compressed stream of the first block not decoded.
+  position: usize,
+  // Offset in the file of the end of the compressed stream, once decoded.
+  end: Option<usize>
+}
+
+impl MamDecoder {
+
+  pub(crate) fn new() -> MamDecoder {
+    MamDecoder {
+      output: std::vec::Vec::new(),
+      position: 0,
+      end: None
+    }
+  }
+
+  /// Decodes the blocks of `content`, the first bytes of the file, which
+  /// were not decoded yet. Returns the length of the file once its stream is
+  /// complete, `None` if more bytes are needed. `complete` tells that
+  /// `content` holds all the data there is.
+  ///
+  /// The stream may end with padding the decoder does not read: with a
+  /// checksum, the length is the one which matches it.
+  pub(crate) fn decode(&mut self, content: &[u8], complete: bool)
+      -> super::Result<Option<usize>> {
+    let (uncompressed_size, start) = mam_header(content)?;
+    let end = match self.end {
+      Some(end) => end,
+      None => {
+        match decompress_blocks(&content[start ..], uncompressed_size,
+            &mut self.output, &mut self.position)? {
+          true => *self.end.insert(start + self.position),
+          false if complete =>
+            return Err(super::error::Error::CorruptedCompressedData),
+          false => return Ok(None)
+        }
+      }
+    };
+    if !has_checksum(content) {
+      return Ok(Some(end));
+    }
+    if content.len() < end + STREAM_PADDING && !complete {
+      return Ok(None);
+    }
+
+    let (expected, computed) = checksums(&content[.. end])?;
+    let mut crc = computed;
+    for length in end ..= std::cmp::min(end + STREAM_PADDING, content.len()) {
+      if length > end {
+        crc = crc32(crc, &content[length - 1 .. length]);
+      }
+      if crc == expected {
+        return Ok(Some(length));
+      }
+    }
+    Err(super::error::Error::ChecksumMismatch(expected, computed))
+  }
+
+  /// Returns the decompressed data, complete once `decode` returned a length.
+  pub(crate) fn into_output(self) -> std::vec::Vec<u8> {
+    self.output
+  }
+}
+
+/// Returns the checksum stored in a `MAM\x84` file and the one computed over
+/// `content`, the checksum field being zeroed.
+fn checksums(content: &[u8]) -> super::Result<(u32, u32)> {
+  let expected: u32 = read(content, Section::CompressionHeader, 0x8, 4)?;
+  let mut computed = crc32(0, &content[0x0 .. 0x8]);
+  computed = crc32(computed, &[0u8; 4]);
+  computed = crc32(computed, &content[0xc ..]);
+  Ok((expected, computed))
+}
+
+/// Checks the format of a `MAM` header, and returns the uncompressed size and
+/// the offset of the compressed stream.
+fn mam_header(content: &[u8]) -> super::Result<(usize, usize)> {
+  let format = super::util::slice(content, Section::CompressionHeader, 0x3,
+    1)?[0];
+  if format & 0x0f != MAM_FORMAT_HUFFMAN {
+    return Err(super::error::Error::UnsupportedCompression(format));
+  }
+  let uncompressed_size = read(content, Section::CompressionHeader, 0x4, 4)?;
+  match format & MAM_FLAG_CHECKSUM != 0 {
+    true => {
+      super::util::slice(content, Section::CompressionHeader, 0x8, 4)?;
+      Ok((uncompressed_size, 0xc))
+    },
+    false => Ok((uncompressed_size, 0x8))
+  }
+}
+
+/// Compresses a Prefetch file into a `MAM` container, with a CRC32 checksum
+/// if `checksum` is set (`MAM\x84`, `MAM\x04` otherwise).
+///
+/// # Example
+///
+/// ```
+/// use libprefetch::Prefetch;
+/// use libprefetch::compression::compress_mam;
+///
+/// let data = std::fs::read("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+/// let compressed = compress_mam(&data, true);
+///
+/// let prefetch = Prefetch::new(&compressed[..]).unwrap();
+/// assert_eq!("WUAUCLT.EXE", prefetch.name());
+/// ```
+pub fn compress_mam(content: &[u8], checksum: bool) -> std::vec::Vec<u8> {
+  let mut output = MAM_SIGNATURE.to_vec();
+  output.push(if checksum {
+    MAM_FORMAT_HUFFMAN | MAM_FLAG_CHECKSUM
+  } else {
+    MAM_FORMAT_HUFFMAN
+  });
+  output.extend(&(content.len() as u32).to_le_bytes());
+  if checksum {
+    output.extend(&[0u8; 4]);
+  }
+  output.extend(compress(content));
+
+  if checksum {
+    let crc = crc32(0, &output);
+    output[0x8 .. 0xc].copy_from_slice(&crc.to_le_bytes());
+  }
+  output
+}
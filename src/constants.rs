@@ -20,7 +20,15 @@ pub(crate) const FORMAT_WINDOWS_XP_2003: u32 = 0x11;
 pub(crate) const FORMAT_WINDOWS_VISTA_7: u32 = 0x17;
 pub(crate) const FORMAT_WINDOWS_8: u32 = 0x1a;
 pub(crate) const FORMAT_WINDOWS_10: u32 = 0x1e;
+pub(crate) const FORMAT_WINDOWS_11: u32 = 0x1f;
 
 pub(crate) const HEADER_LENGTH: usize = 0x54;
 pub(crate) const HEADER_CONSTANT_FIELD: [u8; 4] = [0x53, 0x43, 0x43, 0x41];
 pub(crate) const MAX_FILENAME_LENGTH: usize = 29;
+
+// The file information block follows the header, and the metrics array
+// follows it.
+pub(crate) const FILE_INFORMATION_END_XP_2003: usize = 0x98;
+pub(crate) const FILE_INFORMATION_END_VISTA_7: usize = 0xf0;
+pub(crate) const FILE_INFORMATION_END_WINDOWS_8: usize = 0x130;
+pub(crate) const FILE_INFORMATION_END_WINDOWS_10: usize = 0x134;
@@ -0,0 +1,93 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+use std;
+
+/// An event emitted while parsing a Prefetch file.
+///
+/// Events are handed to the callback given to `Prefetch::with_diagnostics`.
+/// With the `log` feature, `Prefetch::new` forwards them to the `log` crate.
+///
+/// # Example
+///
+/// ```
+/// use libprefetch::Prefetch;
+/// use libprefetch::diagnostics::Event;
+///
+/// let file = std::fs::File::open("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+///
+/// let prefetch = Prefetch::with_diagnostics(file, |event: &Event| {
+///   eprintln!("{}", event);
+/// }).unwrap();
+/// ```
+#[derive(Debug)]
+pub enum Event<'a> {
+
+  /// The file was `MAM` compressed (compressed size, uncompressed size,
+  /// whether a checksum was verified).
+  Decompressed(usize, usize, bool),
+
+  /// The format version was detected.
+  VersionDetected(super::FormatVersion),
+
+  /// Bounds of a section, as found in the file information (section, offset,
+  /// length).
+  SectionBounds(super::Section, usize, usize),
+
+  /// The size stored in the header does not match the size of the data
+  /// (stored, actual).
+  SizeMismatch(usize, usize),
+
+  /// A section could not be parsed and is skipped, the error is returned when
+  /// the section is accessed.
+  SectionSkipped(super::Section, &'a super::Error)
+}
+
+impl<'a> std::fmt::Display for Event<'a> {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match *self {
+      Event::Decompressed(c, u, true) =>
+        write!(f, "decompressed {} bytes into {} bytes, checksum verified", c, u),
+      Event::Decompressed(c, u, false) =>
+        write!(f, "decompressed {} bytes into {} bytes", c, u),
+      Event::VersionDetected(v) => write!(f, "format version: {}", v),
+      Event::SectionBounds(s, o, l) =>
+        write!(f, "{}: {} bytes at offset {:#x}", s, l, o),
+      Event::SizeMismatch(s, a) =>
+        write!(f, "the header announces {} bytes, the data is {} bytes", s, a),
+      Event::SectionSkipped(s, e) => write!(f, "{} skipped: {}", s, e)
+    }
+  }
+}
+
+/// Default destination of the events of `Prefetch::new`.
+#[cfg(feature = "log")]
+pub(crate) fn log(event: &Event) {
+  match *event {
+    Event::SizeMismatch(..) | Event::SectionSkipped(..) =>
+      log::warn!("{}", event),
+    _ => log::debug!("{}", event)
+  }
+}
+
+/// Default destination of the events of `Prefetch::new`.
+#[cfg(not(feature = "log"))]
+pub(crate) fn log(_event: &Event) {
+}
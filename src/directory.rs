@@ -0,0 +1,410 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+//! Directory trees of the volumes.
+//!
+//! A `DirectoryTree` is built for each volume from its directory list, and
+//! the metric filenames are attached to the directory which holds them.
+//! Names are compared without case, as NTFS does: each character is
+//! upper-cased on its own.
+//!
+//! The nodes are stored in a single vector and refer to each other with
+//! `NodeId`s. With the `serde` feature, a tree is serialized as nested
+//! structures with the `name`, `listed`, `files` and `children` of each
+//! directory. Trees with directories more than 1024 levels deep fail to
+//! serialize, rather than overflowing the stack.
+//!
+//! # Example
+//!
+//! ```
+//! use libprefetch::Prefetch;
+//! use libprefetch::directory::DirectoryTree;
+//!
+//! let prefetch =
+//!     Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+//!
+//! let trees = DirectoryTree::from_prefetch(&prefetch).unwrap();
+//! let tree = &trees[0];
+//! let system32 = tree.find(r"\Device\HarddiskVolume2\Windows\System32")
+//!   .unwrap();
+//! assert_eq!(r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32", tree.path(system32));
+//! for file in tree.node(system32).files() {
+//!   println!("{} (metric #{})", file.name(), file.metric_id());
+//! }
+//! for child in tree.node(system32).children() {
+//!   println!("{}", tree.node(*child).name());
+//! }
+//! ```
+
+use std;
+use super::hash::upper_case;
+use super::utf16::Utf16String;
+
+const SEPARATOR: u16 = b'\\' as u16;
+
+/// Longest path NTFS allows, in UTF-16 units. It bounds the depth of a tree.
+const MAX_PATH_LENGTH: usize = 32767;
+
+/// Deepest directory serialized: serializers recurse once per level of the
+/// nested output.
+#[cfg(feature = "serde")]
+const MAX_SERIALIZED_DEPTH: usize = 1024;
+
+/// Index of a node in a `DirectoryTree`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// A file of a directory, linked to its metric entry.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileLink {
+  metric_id: usize,
+  name: Utf16String
+}
+
+impl FileLink {
+
+  /// Returns the ID of the metric entry of the file.
+  pub fn metric_id(&self) -> usize {
+    self.metric_id
+  }
+
+  /// Returns the name of the file, without its directory.
+  pub fn name(&self) -> &str {
+    self.name.as_str()
+  }
+
+  /// Returns the name of the file, as stored in the file.
+  pub fn name_utf16(&self) -> &Utf16String {
+    &self.name
+  }
+}
+
+/// A directory of a `DirectoryTree`.
+#[derive(Debug, Clone)]
+pub struct Node {
+  name: Utf16String,
+  key: std::vec::Vec<u16>,
+  parent: Option<NodeId>,
+  children: std::vec::Vec<NodeId>,
+  files: std::vec::Vec<FileLink>,
+  listed: bool
+}
+
+impl Node {
+
+  fn new(name: Utf16String, parent: Option<NodeId>) -> Node {
+    Node {
+      key: upper_case(name.units()),
+      name,
+      parent,
+      children: std::vec::Vec::new(),
+      files: std::vec::Vec::new(),
+      listed: false
+    }
+  }
+
+  /// Returns the name of the directory, the device path for the root.
+  pub fn name(&self) -> &str {
+    self.name.as_str()
+  }
+
+  /// Returns the name of the directory, as stored in the file.
+  pub fn name_utf16(&self) -> &Utf16String {
+    &self.name
+  }
+
+  /// Returns the parent directory, `None` for the root.
+  pub fn parent(&self) -> Option<NodeId> {
+    self.parent
+  }
+
+  /// Returns the subdirectories, in the order they were inserted.
+  pub fn children(&self) -> &[NodeId] {
+    &self.children
+  }
+
+  /// Returns the files of the directory found in the metrics.
+  pub fn files(&self) -> &[FileLink] {
+    &self.files
+  }
+
+  /// Returns whether the directory is in the directory list of the volume,
+  /// rather than only being the parent of another directory or file.
+  pub fn is_listed(&self) -> bool {
+    self.listed
+  }
+}
+
+/// The directory tree of a volume.
+#[derive(Debug, Clone)]
+pub struct DirectoryTree {
+  nodes: std::vec::Vec<Node>,
+  // The device path and the `\VOLUME{...}` identifier, upper-cased.
+  prefixes: [std::vec::Vec<u16>; 2]
+}
+
+impl DirectoryTree {
+
+  /// Constructs the tree of the directories of `volume`, without files.
+  ///
+  /// Paths may start with the device path of the volume or with its
+  /// `\VOLUME{...}` identifier.
+  pub fn new(volume: &super::volume::VolumeEntry) -> DirectoryTree {
+    let device = volume.device_path_utf16().units();
+    let device = &device[.. device.iter().rposition(|&u| u != SEPARATOR)
+      .map_or(0, |i| i + 1)];
+    let id: std::vec::Vec<u16> = volume.volume_id().to_string()
+      .encode_utf16().collect();
+    let mut root = Node::new(Utf16String::new(device.to_vec()), None);
+    root.listed = true;
+    let mut tree = DirectoryTree {
+      nodes: vec![root],
+      prefixes: [upper_case(device), upper_case(&id)]
+    };
+    for directory in volume.directories_utf16() {
+      if let Some(node) = tree.insert_directory(directory.units()) {
+        tree.nodes[node.0].listed = true;
+      }
+    }
+    tree
+  }
+
+  /// Constructs the trees of the volumes of `prefetch`, with the files of the
+  /// metrics attached.
+  pub fn from_prefetch(prefetch: &super::Prefetch)
+      -> super::Result<std::vec::Vec<DirectoryTree>> {
+    let mut trees: std::vec::Vec<DirectoryTree> = prefetch.volumes()?
+      .map(DirectoryTree::new)
+      .collect();
+    for metric in prefetch.metrics()? {
+      for tree in trees.iter_mut() {
+        if tree.insert_file(metric).is_some() {
+          break;
+        }
+      }
+    }
+    Ok(trees)
+  }
+
+  /// Attaches the file of `metric` to its directory, which is created if
+  /// needed. Returns the directory, or `None` if the file is on another
+  /// volume or if its path is longer than NTFS allows.
+  pub fn insert_file(&mut self, metric: &super::metric::MetricEntry)
+      -> Option<NodeId> {
+    let units = metric.filename_utf16().units();
+    if units.len() > MAX_PATH_LENGTH {
+      return None;
+    }
+    let split = units.iter().rposition(|&u| u == SEPARATOR)?;
+    let node = self.insert_directory(&units[.. split])?;
+    self.nodes[node.0].files.push(FileLink {
+      metric_id: metric.id(),
+      name: Utf16String::new(units[split + 1 ..].to_vec())
+    });
+    Some(node)
+  }
+
+  /// Returns the root, which stands for the volume.
+  pub fn root(&self) -> NodeId {
+    NodeId(0)
+  }
+
+  /// Returns the node `id`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `id` does not belong to the tree.
+  pub fn node(&self, id: NodeId) -> &Node {
+    &self.nodes[id.0]
+  }
+
+  /// Returns the number of directories, the root included.
+  pub fn len(&self) -> usize {
+    self.nodes.len()
+  }
+
+  /// Returns whether the tree only has its root.
+  pub fn is_empty(&self) -> bool {
+    self.nodes.len() == 1
+  }
+
+  /// Returns the directory at `path`, compared without case.
+  pub fn find(&self, path: &str) -> Option<NodeId> {
+    let units: std::vec::Vec<u16> = path.encode_utf16().collect();
+    let mut node = self.root();
+    for name in self.components(&units)? {
+      node = self.child(node, name)?;
+    }
+    Some(node)
+  }
+
+  /// Returns the full path of the directory `id`.
+  pub fn path(&self, id: NodeId) -> std::string::String {
+    self.path_utf16(id).as_str().to_string()
+  }
+
+  /// Returns the full path of the directory `id`, as stored in the file.
+  pub fn path_utf16(&self, id: NodeId) -> Utf16String {
+    let mut names = std::vec::Vec::new();
+    let mut node = Some(id);
+    while let Some(id) = node {
+      names.push(self.nodes[id.0].name.units());
+      node = self.nodes[id.0].parent;
+    }
+    let mut path = std::vec::Vec::new();
+    for (i, name) in names.iter().rev().enumerate() {
+      if i > 0 {
+        path.push(SEPARATOR);
+      }
+      path.extend_from_slice(name);
+    }
+    Utf16String::new(path)
+  }
+
+  /// Returns an iterator through the directories, depth first: each directory
+  /// comes before its subdirectories.
+  pub fn iter(&self) -> Iter<'_> {
+    Iter {
+      tree: self,
+      stack: vec![self.root()]
+    }
+  }
+
+  /// Inserts the directory at `path` and its missing parents. Returns `None`
+  /// if `path` is on another volume or longer than NTFS allows.
+  fn insert_directory(&mut self, path: &[u16]) -> Option<NodeId> {
+    if path.len() > MAX_PATH_LENGTH {
+      return None;
+    }
+    let names: std::vec::Vec<&[u16]> = self.components(path)?.collect();
+    let mut node = self.root();
+    for name in names {
+      node = match self.child(node, name) {
+        Some(child) => child,
+        None => {
+          let child = NodeId(self.nodes.len());
+          self.nodes.push(Node::new(Utf16String::new(name.to_vec()),
+            Some(node)));
+          self.nodes[node.0].children.push(child);
+          child
+        }
+      };
+    }
+    Some(node)
+  }
+
+  fn child(&self, node: NodeId, name: &[u16]) -> Option<NodeId> {
+    let key = upper_case(name);
+    self.nodes[node.0].children.iter()
+      .cloned()
+      .find(|c| self.nodes[c.0].key == key)
+  }
+
+  /// Strips the device of `path` and returns the names which follow it.
+  fn components<'p>(&self, path: &'p [u16])
+      -> Option<impl Iterator<Item = &'p [u16]>> {
+    let rest = self.prefixes.iter().find_map(|prefix| {
+      let rest = path.get(prefix.len() ..)?;
+      match upper_case(&path[.. prefix.len()]) == *prefix
+          && rest.first().is_none_or(|&u| u == SEPARATOR) {
+        true => Some(rest),
+        false => None
+      }
+    })?;
+    Some(rest.split(|&u| u == SEPARATOR).filter(|name| !name.is_empty()))
+  }
+}
+
+/// Iterator through the directories of a `DirectoryTree`, depth first.
+pub struct Iter<'t> {
+  tree: &'t DirectoryTree,
+  stack: std::vec::Vec<NodeId>
+}
+
+impl<'t> Iterator for Iter<'t> {
+  type Item = NodeId;
+
+  fn next(&mut self) -> Option<NodeId> {
+    let id = self.stack.pop()?;
+    self.stack.extend(self.tree.nodes[id.0].children.iter().rev());
+    Some(id)
+  }
+}
+
+// A directory is serialized from the tree as it goes, rather than from a
+// nested copy of the tree.
+#[cfg(feature = "serde")]
+struct SerializedNode<'t> {
+  tree: &'t DirectoryTree,
+  id: NodeId,
+  depth: usize
+}
+
+#[cfg(feature = "serde")]
+impl<'t> serde::Serialize for SerializedNode<'t> {
+
+  fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where S: serde::Serializer {
+    if self.depth > MAX_SERIALIZED_DEPTH {
+      return Err(<S::Error as serde::ser::Error>::custom(format!(
+        "directories are nested more than {} levels deep",
+        MAX_SERIALIZED_DEPTH)));
+    }
+    let node = self.tree.node(self.id);
+    let mut s = serializer.serialize_struct("Node", 4)?;
+    serde::ser::SerializeStruct::serialize_field(&mut s, "name", &node.name)?;
+    serde::ser::SerializeStruct::serialize_field(&mut s, "listed", &node.listed)?;
+    serde::ser::SerializeStruct::serialize_field(&mut s, "files", &node.files)?;
+    serde::ser::SerializeStruct::serialize_field(&mut s, "children",
+      &SerializedChildren(self, &node.children))?;
+    serde::ser::SerializeStruct::end(s)
+  }
+}
+
+#[cfg(feature = "serde")]
+struct SerializedChildren<'n, 't>(&'n SerializedNode<'t>, &'t [NodeId]);
+
+#[cfg(feature = "serde")]
+impl<'n, 't> serde::Serialize for SerializedChildren<'n, 't> {
+
+  fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where S: serde::Serializer {
+    let parent = self.0;
+    serializer.collect_seq(self.1.iter().map(|&id| SerializedNode {
+      tree: parent.tree,
+      id,
+      depth: parent.depth + 1
+    }))
+  }
+}
+
+// The tree is serialized from its root, as nested directories.
+#[cfg(feature = "serde")]
+impl serde::Serialize for DirectoryTree {
+
+  fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where S: serde::Serializer {
+    let root = SerializedNode {
+      tree: self,
+      id: self.root(),
+      depth: 0
+    };
+    serde::Serialize::serialize(&root, serializer)
+  }
+}
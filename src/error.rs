@@ -19,6 +19,54 @@
 use std;
 use std::fmt;
 
+/// Sections of a Prefetch file, as named by `Error::OutOfBounds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+
+  /// The `MAM` header of a compressed file.
+  CompressionHeader,
+
+  /// The SCCA header.
+  Header,
+
+  /// The file information block, following the header.
+  FileInformation,
+
+  /// The file metrics array.
+  Metrics,
+
+  /// The trace chain array.
+  TraceChain,
+
+  /// The filename strings.
+  Filenames,
+
+  /// The volumes information.
+  Volumes,
+
+  /// The directory strings of a volume.
+  Directories,
+
+  /// The NTFS file references of a volume.
+  FileReferences
+}
+
+impl fmt::Display for Section {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Section::CompressionHeader => write!(f, "compression header"),
+      Section::Header => write!(f, "header"),
+      Section::FileInformation => write!(f, "file information"),
+      Section::Metrics => write!(f, "file metrics"),
+      Section::TraceChain => write!(f, "trace chain"),
+      Section::Filenames => write!(f, "filename strings"),
+      Section::Volumes => write!(f, "volumes information"),
+      Section::Directories => write!(f, "directory strings"),
+      Section::FileReferences => write!(f, "file references")
+    }
+  }
+}
+
 /// Errors related to the process of parsing and reading.
 #[derive(Debug)]
 pub enum Error {
@@ -32,10 +80,82 @@ pub enum Error {
   /// Basic IO error.
   IOError(std::io::Error),
 
-  /// Not supported or not implemented yet (like for Windows 10).
+  /// Not supported or not implemented yet.
   NotImplemented,
 
-  LZXPressError(lzxpress::error::Error),
+  /// The LZXPRESS Huffman data of a compressed file is corrupted.
+  CorruptedCompressedData,
+
+  /// The decompressed data does not have the size announced by the header
+  /// (expected, actual).
+  UncompressedSizeMismatch(usize, usize),
+
+  /// The `MAM` header announces an unsupported compression format.
+  UnsupportedCompression(u8),
+
+  /// The CRC32 checksum of a compressed file does not match its content
+  /// (stored, computed).
+  ChecksumMismatch(u32, u32),
+
+  /// A read goes past the end of a section or of the file (section, offset,
+  /// length), in bytes.
+  ///
+  /// The offset is the one found in the file: from the start of the file for
+  /// the header, the file information and the sections it lists, and from the
+  /// start of the enclosing section for strings and volume entries.
+  OutOfBounds(Section, usize, usize),
+
+  /// A range of entries goes past the end of a section (section, index of
+  /// the first entry, number of entries).
+  EntryOutOfBounds(Section, usize, usize),
+
+  /// A string is not valid UTF-16 (index of the first invalid unit).
+  InvalidUtf16(usize),
+
+  /// A FILETIME is zero, that is not set.
+  UnsetTime,
+
+  /// A FILETIME cannot be represented by the target type (raw value).
+  TimeOutOfRange(u64),
+
+  /// A section of a deserialized file was not available when the file was
+  /// serialized, as it could not be parsed.
+  SectionUnavailable(Section),
+
+  /// A registry hive is malformed (offset in the hive file, in bytes).
+  InvalidHive(usize),
+
+  /// A registry key is missing from a hive (path of the key).
+  RegistryKeyNotFound(std::string::String),
+}
+
+impl Error {
+
+  // Errors of the sections are kept by the parser and handed over to the
+  // caller later. They never wrap an IO error, which cannot be cloned.
+  pub(crate) fn duplicate(&self) -> Error {
+    match *self {
+      Error::UnknownFormatVersion(v) => Error::UnknownFormatVersion(v),
+      Error::NotPrefetchFile => Error::NotPrefetchFile,
+      Error::IOError(ref e) => Error::IOError(
+        std::io::Error::new(e.kind(), e.to_string())),
+      Error::NotImplemented => Error::NotImplemented,
+      Error::CorruptedCompressedData => Error::CorruptedCompressedData,
+      Error::UncompressedSizeMismatch(e, a) =>
+        Error::UncompressedSizeMismatch(e, a),
+      Error::UnsupportedCompression(v) => Error::UnsupportedCompression(v),
+      Error::ChecksumMismatch(s, c) => Error::ChecksumMismatch(s, c),
+      Error::OutOfBounds(s, o, l) => Error::OutOfBounds(s, o, l),
+      Error::EntryOutOfBounds(s, i, n) => Error::EntryOutOfBounds(s, i, n),
+      Error::InvalidUtf16(i) => Error::InvalidUtf16(i),
+      Error::UnsetTime => Error::UnsetTime,
+      Error::TimeOutOfRange(t) => Error::TimeOutOfRange(t),
+      Error::SectionUnavailable(s) => Error::SectionUnavailable(s),
+      Error::InvalidHive(o) => Error::InvalidHive(o),
+      Error::RegistryKeyNotFound(ref k) =>
+        Error::RegistryKeyNotFound(k.clone())
+    }
+  }
 }
 
 /// Classic custom Result type.
@@ -47,9 +167,18 @@ impl fmt::Display for Error {
       Error::UnknownFormatVersion(v) => write!(f, "Unknown prefetch format version {:02x}", v),
       Error::NotPrefetchFile => write!(f, "This is not a prefetch file"),
       Error::IOError(ref e) => e.fmt(f),
-      Error::LZXPressError(lzxpress::error::Error::MemLimit) => write!(f, "LZXPress: not enough memory"),
-      Error::LZXPressError(lzxpress::error::Error::CorruptedData) => write!(f, "LZXPress: corrupted data"),
-      Error::LZXPressError(lzxpress::error::Error::Other) => write!(f, "LZXPress: unknown error"),
+      Error::CorruptedCompressedData => write!(f, "LZXPress: corrupted data"),
+      Error::UncompressedSizeMismatch(e, a) => write!(f, "LZXPress: expected {} uncompressed bytes, got {}", e, a),
+      Error::UnsupportedCompression(v) => write!(f, "Unsupported compression format {:02x}", v),
+      Error::ChecksumMismatch(s, c) => write!(f, "Checksum mismatch: stored {:08x}, computed {:08x}", s, c),
+      Error::OutOfBounds(s, o, l) => write!(f, "Out of bounds read in the {}: {} bytes at offset {:#x}", s, l, o),
+      Error::EntryOutOfBounds(s, i, n) => write!(f, "Out of bounds entries in the {}: {} entries from #{}", s, n, i),
+      Error::InvalidUtf16(i) => write!(f, "Invalid UTF-16 unit at index {}", i),
+      Error::UnsetTime => write!(f, "The time is not set"),
+      Error::TimeOutOfRange(t) => write!(f, "FILETIME {:#x} is out of range", t),
+      Error::SectionUnavailable(s) => write!(f, "The {} was not available when serialized", s),
+      Error::InvalidHive(o) => write!(f, "Invalid registry hive at offset {:#x}", o),
+      Error::RegistryKeyNotFound(ref k) => write!(f, "Registry key {} not found", k),
       Error::NotImplemented => write!(f, "Not implemented yet")
   }
   }
@@ -61,9 +190,18 @@ impl std::error::Error for Error {
       Error::UnknownFormatVersion(_v) => "Unknown prefetch format version.",
       Error::NotPrefetchFile => "This is not a prefetch file",
       Error::IOError(ref e) => e.description(),
-      Error::LZXPressError(lzxpress::error::Error::MemLimit) => "LZXPress: not enough memory",
-      Error::LZXPressError(lzxpress::error::Error::CorruptedData) => "LZXPress: corrupted data",
-      Error::LZXPressError(lzxpress::error::Error::Other) => "LZXPress: unknown error",
+      Error::CorruptedCompressedData => "LZXPress: corrupted data",
+      Error::UncompressedSizeMismatch(_e, _a) => "LZXPress: unexpected uncompressed size",
+      Error::UnsupportedCompression(_v) => "Unsupported compression format",
+      Error::ChecksumMismatch(_s, _c) => "Checksum mismatch",
+      Error::OutOfBounds(_s, _o, _l) => "Out of bounds read",
+      Error::EntryOutOfBounds(_s, _i, _n) => "Out of bounds entries",
+      Error::InvalidUtf16(_i) => "Invalid UTF-16 string",
+      Error::UnsetTime => "The time is not set",
+      Error::TimeOutOfRange(_t) => "FILETIME out of range",
+      Error::SectionUnavailable(_s) => "Section unavailable when serialized",
+      Error::InvalidHive(_o) => "Invalid registry hive",
+      Error::RegistryKeyNotFound(ref _k) => "Registry key not found",
       Error::NotImplemented => "Not implemented yet"
     }
   }
@@ -0,0 +1,196 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+//! Resolution of the full path of the executable.
+//!
+//! The header only stores the name of the executable, truncated to 29
+//! characters. The full path is searched among the metric filenames, which
+//! list the executable itself, and checked against the path hash of the
+//! header. When no filename matches the hash, the name is also looked up in
+//! every directory of the volumes.
+//!
+//! # Example
+//!
+//! ```
+//! use libprefetch::Prefetch;
+//! use libprefetch::executable::Confidence;
+//! use libprefetch::hash::HashAlgorithm;
+//!
+//! let prefetch =
+//!     Prefetch::from_path("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+//!
+//! let executable = prefetch.executable_path().unwrap();
+//! assert_eq!(r"\DEVICE\HARDDISKVOLUME1\WINDOWS\SYSTEM32\WUAUCLT.EXE",
+//!   executable.path());
+//! assert_eq!(Confidence::HashMatch(HashAlgorithm::WindowsXp),
+//!   executable.confidence());
+//! ```
+
+use std;
+use super::hash::{HashAlgorithm, upper_case};
+use super::utf16::Utf16String;
+
+/// How much a resolved path can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confidence {
+
+  /// The path hashes to the hash of the header, with the given algorithm.
+  HashMatch(HashAlgorithm),
+
+  /// No path matches the hash, but a single metric filename has the name of
+  /// the executable.
+  UniqueName,
+
+  /// No path matches the hash and several metric filenames have the name of
+  /// the executable, the first one is returned.
+  Ambiguous
+}
+
+impl std::fmt::Display for Confidence {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match *self {
+      Confidence::HashMatch(algorithm) =>
+        write!(f, "hash match ({})", algorithm),
+      Confidence::UniqueName => write!(f, "unique name match"),
+      Confidence::Ambiguous => write!(f, "ambiguous")
+    }
+  }
+}
+
+/// The full path of the executable of a Prefetch file.
+#[derive(Debug, Clone)]
+pub struct ExecutablePath {
+  path: Utf16String,
+  confidence: Confidence,
+  candidates: std::vec::Vec<Utf16String>
+}
+
+impl ExecutablePath {
+
+  /// Returns the path.
+  pub fn path(&self) -> &str {
+    self.path.as_str()
+  }
+
+  /// Returns the path, as stored in the file.
+  pub fn path_utf16(&self) -> &Utf16String {
+    &self.path
+  }
+
+  /// Returns how much the path can be trusted.
+  pub fn confidence(&self) -> Confidence {
+    self.confidence
+  }
+
+  /// Returns the metric filenames which have the name of the executable, in
+  /// the order of the file.
+  pub fn candidates(&self) -> &[Utf16String] {
+    &self.candidates
+  }
+}
+
+/// Resolves the path of the executable of `prefetch`. Sections which fail to
+/// parse are left out.
+pub(crate) fn resolve(prefetch: &super::Prefetch) -> Option<ExecutablePath> {
+  let name = upper_case(prefetch.name_utf16().units());
+  // A name of the maximum length may have been truncated.
+  let truncated = name.len() >= super::constants::MAX_FILENAME_LENGTH;
+  let matches = |filename: &[u16]| {
+    let filename = upper_case(file_name(filename));
+    match truncated {
+      true => filename.starts_with(&name),
+      false => filename == name
+    }
+  };
+
+  let candidates: std::vec::Vec<Utf16String> = match prefetch.metrics() {
+    Ok(metrics) => metrics
+      .map(|m| m.filename_utf16())
+      .filter(|f| matches(f.units()))
+      .cloned()
+      .collect(),
+    Err(_) => std::vec::Vec::new()
+  };
+
+  let verify = |path: &[u16]| {
+    let path = upper_case(path);
+    HashAlgorithm::candidates(prefetch.version()).iter()
+      .cloned()
+      .find(|algorithm| algorithm.hash_units(&path) == prefetch.hash())
+  };
+
+  for candidate in &candidates {
+    if let Some(algorithm) = verify(candidate.units()) {
+      return Some(ExecutablePath {
+        path: candidate.clone(),
+        confidence: Confidence::HashMatch(algorithm),
+        candidates
+      });
+    }
+  }
+
+  // The executable may be missing from the metrics, or listed under a
+  // different volume path: try its name in every directory.
+  let mut names: std::vec::Vec<&[u16]> = candidates.iter()
+    .map(|c| file_name(c.units()))
+    .collect();
+  if !truncated {
+    names.push(prefetch.name_utf16().units());
+  }
+  if let Ok(volumes) = prefetch.volumes() {
+    for volume in volumes {
+      for directory in volume.directories_utf16() {
+        for name in &names {
+          let mut path = directory.units().to_vec();
+          if path.last() != Some(&(b'\\' as u16)) {
+            path.push(b'\\' as u16);
+          }
+          path.extend_from_slice(name);
+          if let Some(algorithm) = verify(&path) {
+            return Some(ExecutablePath {
+              path: Utf16String::new(path),
+              confidence: Confidence::HashMatch(algorithm),
+              candidates
+            });
+          }
+        }
+      }
+    }
+  }
+
+  let confidence = match candidates.len() {
+    0 => return None,
+    1 => Confidence::UniqueName,
+    _ => Confidence::Ambiguous
+  };
+  Some(ExecutablePath {
+    path: candidates[0].clone(),
+    confidence,
+    candidates
+  })
+}
+
+// Returns the units after the last backslash.
+fn file_name(path: &[u16]) -> &[u16] {
+  match path.iter().rposition(|&u| u == b'\\' as u16) {
+    Some(i) => &path[i + 1 ..],
+    None => path
+  }
+}
@@ -0,0 +1,181 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+//! Windows FILETIME timestamps.
+
+use std;
+
+/// Number of 100 nanoseconds intervals in a second.
+const INTERVALS_PER_SECOND: u64 = 10_000_000;
+
+/// Seconds between 1601-01-01, the FILETIME epoch, and 1970-01-01.
+const UNIX_EPOCH_SECONDS: u64 = 11_644_473_600;
+
+/// Seconds of 9999-12-31T23:59:59Z since 1601-01-01, the last second RFC 3339
+/// can represent.
+const RFC3339_MAX_SECONDS: u64 = 265_046_774_399;
+
+/// A Windows FILETIME: the number of 100 nanoseconds intervals since
+/// 1601-01-01 00:00:00 UTC.
+///
+/// A zero FILETIME means that the time is not set, conversions then fail
+/// with `Error::UnsetTime`. Conversions to a type which cannot hold the value
+/// fail with `Error::TimeOutOfRange`.
+///
+/// # Example
+///
+/// ```
+/// use libprefetch::Prefetch;
+///
+/// let prefetch =
+///     Prefetch::from_path("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+///
+/// let time = prefetch.last_execution_time();
+/// assert_eq!(129453035816965472, time.value());
+/// assert_eq!("2011-03-22T21:39:41.6965472Z", time.to_rfc3339().unwrap());
+/// assert!(time.to_system_time().is_ok());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct FileTime(u64);
+
+impl FileTime {
+
+  /// Constructs a `FileTime` from its raw value.
+  pub fn new(value: u64) -> FileTime {
+    FileTime(value)
+  }
+
+  /// Returns the raw value.
+  pub fn value(&self) -> u64 {
+    self.0
+  }
+
+  /// Returns whether the time is not set.
+  pub fn is_zero(&self) -> bool {
+    self.0 == 0
+  }
+
+  /// Returns the seconds and the nanoseconds since 1601-01-01.
+  fn split(&self) -> super::Result<(u64, u32)> {
+    if self.is_zero() {
+      return Err(super::error::Error::UnsetTime);
+    }
+    Ok((self.0 / INTERVALS_PER_SECOND,
+      (self.0 % INTERVALS_PER_SECOND) as u32 * 100))
+  }
+
+  /// Returns the seconds and the nanoseconds since 1970-01-01, the seconds
+  /// being negative before.
+  #[cfg(any(feature = "chrono", feature = "time"))]
+  fn unix(&self) -> super::Result<(i64, u32)> {
+    let (seconds, nanoseconds) = self.split()?;
+    Ok((seconds as i64 - UNIX_EPOCH_SECONDS as i64, nanoseconds))
+  }
+
+  /// Converts the time to a `std::time::SystemTime`.
+  pub fn to_system_time(&self) -> super::Result<std::time::SystemTime> {
+    let (seconds, nanoseconds) = self.split()?;
+    let epoch = std::time::Duration::new(UNIX_EPOCH_SECONDS, 0);
+    let time = std::time::Duration::new(seconds, nanoseconds);
+    let result = if time >= epoch {
+      std::time::UNIX_EPOCH.checked_add(time - epoch)
+    } else {
+      std::time::UNIX_EPOCH.checked_sub(epoch - time)
+    };
+    result.ok_or(super::error::Error::TimeOutOfRange(self.0))
+  }
+
+  /// Formats the time as RFC 3339, in UTC with a precision of 100
+  /// nanoseconds, e.g. `2011-03-22T21:39:41.6965472Z`.
+  ///
+  /// RFC 3339 only allows four digits years: times after 9999 fail with
+  /// `Error::TimeOutOfRange`.
+  pub fn to_rfc3339(&self) -> super::Result<std::string::String> {
+    let (seconds, nanoseconds) = self.split()?;
+    if seconds > RFC3339_MAX_SECONDS {
+      return Err(super::error::Error::TimeOutOfRange(self.0));
+    }
+    let (year, month, day) = civil_from_days(
+      (seconds / 86400) as i64 - (UNIX_EPOCH_SECONDS / 86400) as i64);
+    let time = seconds % 86400;
+
+    let mut s = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day,
+      time / 3600, time / 60 % 60, time % 60);
+    if nanoseconds != 0 {
+      let fraction = format!("{:07}", nanoseconds / 100);
+      s.push('.');
+      s.push_str(fraction.trim_end_matches('0'));
+    }
+    s.push('Z');
+    Ok(s)
+  }
+
+  /// Converts the time to a `chrono::DateTime<chrono::Utc>`.
+  #[cfg(feature = "chrono")]
+  pub fn to_chrono(&self)
+      -> super::Result<chrono::DateTime<chrono::Utc>> {
+    let (seconds, nanoseconds) = self.unix()?;
+    chrono::DateTime::from_timestamp(seconds, nanoseconds)
+      .ok_or(super::error::Error::TimeOutOfRange(self.0))
+  }
+
+  /// Converts the time to a `time::OffsetDateTime`, in UTC.
+  #[cfg(feature = "time")]
+  pub fn to_offset_date_time(&self) -> super::Result<time::OffsetDateTime> {
+    let (seconds, nanoseconds) = self.unix()?;
+    time::OffsetDateTime::from_unix_timestamp_nanos(
+      seconds as i128 * 1_000_000_000 + nanoseconds as i128)
+      .map_err(|_| super::error::Error::TimeOutOfRange(self.0))
+  }
+}
+
+// Days since 1970-01-01 to a (year, month, day) date of the proleptic
+// Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+  (year, month, day)
+}
+
+impl From<u64> for FileTime {
+
+  fn from(value: u64) -> FileTime {
+    FileTime(value)
+  }
+}
+
+impl std::fmt::Display for FileTime {
+
+  /// Formats the time as RFC 3339, or its raw value if it cannot be.
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self.to_rfc3339() {
+      Ok(s) => f.write_str(&s),
+      Err(_) => write!(f, "{:#x}", self.0)
+    }
+  }
+}
@@ -0,0 +1,167 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+//! Prefetch path hashes.
+//!
+//! The name of a Prefetch file ends with a hash of the NT device path of the
+//! executable, such as `\DEVICE\HARDDISKVOLUME1\WINDOWS\SYSTEM32\CMD.EXE`,
+//! which is also stored in the header. Windows computes it on the upper-case
+//! path, encoded in UTF-16 little endian, with one of the algorithms below.
+//!
+//! # Example
+//!
+//! ```
+//! use libprefetch::hash::{self, HashAlgorithm};
+//! use libprefetch::Prefetch;
+//!
+//! let prefetch =
+//!     Prefetch::from_path("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+//! let path = r"\DEVICE\HARDDISKVOLUME1\WINDOWS\SYSTEM32\WUAUCLT.EXE";
+//!
+//! assert_eq!(0x399a8e72, hash::windows_xp(path));
+//! assert_eq!(Some(HashAlgorithm::WindowsXp), prefetch.verify_hash(path));
+//! assert_eq!(None, prefetch.verify_hash(r"\DEVICE\HARDDISKVOLUME1\CMD.EXE"));
+//! ```
+
+use std;
+
+/// Algorithms used by Windows to hash the path of an executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+
+  /// Windows XP and Windows 2003.
+  WindowsXp,
+
+  /// Windows Vista and later.
+  WindowsVista,
+
+  /// Windows 2008, which hashes eight bytes at a time. It always gives the
+  /// same value as `WindowsVista`.
+  Windows2008
+}
+
+impl HashAlgorithm {
+
+  /// Returns the hash of `path`, upper-cased first.
+  pub fn hash(self, path: &str) -> u32 {
+    self.hash_units(&upper_case(&path.encode_utf16().collect::<std::vec::Vec<_>>()))
+  }
+
+  /// Returns the hash of a path given as UTF-16 units, which are hashed as
+  /// is.
+  pub fn hash_units(self, units: &[u16]) -> u32 {
+    let bytes: std::vec::Vec<u8> = units.iter()
+      .flat_map(|u| u.to_le_bytes())
+      .collect();
+    match self {
+      HashAlgorithm::WindowsXp => hash_xp(&bytes),
+      HashAlgorithm::WindowsVista => hash_vista(&bytes),
+      HashAlgorithm::Windows2008 => hash_2008(&bytes)
+    }
+  }
+
+  /// Returns the algorithms which Windows may have used for a file of
+  /// `version`, the most likely first.
+  pub fn candidates(version: super::FormatVersion) -> [HashAlgorithm; 3] {
+    match version {
+      super::FormatVersion::WindowsXp2003 => [HashAlgorithm::WindowsXp,
+        HashAlgorithm::WindowsVista, HashAlgorithm::Windows2008],
+      _ => [HashAlgorithm::WindowsVista, HashAlgorithm::Windows2008,
+        HashAlgorithm::WindowsXp]
+    }
+  }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match *self {
+      HashAlgorithm::WindowsXp => write!(f, "Windows XP"),
+      HashAlgorithm::WindowsVista => write!(f, "Windows Vista"),
+      HashAlgorithm::Windows2008 => write!(f, "Windows 2008")
+    }
+  }
+}
+
+/// Returns the Windows XP and 2003 hash of `path`.
+pub fn windows_xp(path: &str) -> u32 {
+  HashAlgorithm::WindowsXp.hash(path)
+}
+
+/// Returns the Windows Vista and later hash of `path`.
+pub fn windows_vista(path: &str) -> u32 {
+  HashAlgorithm::WindowsVista.hash(path)
+}
+
+/// Returns the Windows 2008 hash of `path`.
+pub fn windows_2008(path: &str) -> u32 {
+  HashAlgorithm::Windows2008.hash(path)
+}
+
+// Characters whose upper case does not fit in a single character, such as
+// `ß`, and unpaired surrogates are kept.
+pub(crate) fn upper_case(units: &[u16]) -> std::vec::Vec<u16> {
+  let mut upper = std::vec::Vec::with_capacity(units.len());
+  let mut buffer = [0u16; 2];
+  for c in std::char::decode_utf16(units.iter().cloned()) {
+    match c {
+      Ok(c) => {
+        let mut chars = c.to_uppercase();
+        let c = match (chars.next(), chars.next()) {
+          (Some(u), None) => u,
+          _ => c
+        };
+        upper.extend_from_slice(c.encode_utf16(&mut buffer));
+      },
+      Err(e) => upper.push(e.unpaired_surrogate())
+    }
+  }
+  upper
+}
+
+fn hash_xp(bytes: &[u8]) -> u32 {
+  let hash = bytes.iter()
+    .fold(0u32, |h, &b| h.wrapping_mul(37).wrapping_add(b as u32))
+    .wrapping_mul(314_159_269);
+  (hash as i32).unsigned_abs() % 1_000_000_007
+}
+
+fn hash_vista(bytes: &[u8]) -> u32 {
+  bytes.iter()
+    .fold(314_159u32, |h, &b| h.wrapping_mul(37).wrapping_add(b as u32))
+}
+
+// Unrolled version of the Vista hash, 442596621 and 803794207 being 37^7 and
+// -37^8 modulo 2^32.
+fn hash_2008(bytes: &[u8]) -> u32 {
+  let mut hash = 314_159u32;
+  let mut i = 0;
+  while i + 8 < bytes.len() {
+    let b = |n: usize| bytes[i + n] as u32;
+    let mut c = b(1).wrapping_mul(37);
+    for n in 2..7 {
+      c = c.wrapping_add(b(n)).wrapping_mul(37);
+    }
+    c = c.wrapping_add(b(0).wrapping_mul(442_596_621)).wrapping_add(b(7));
+    hash = c.wrapping_sub(hash.wrapping_mul(803_794_207));
+    i += 8;
+  }
+  bytes[i..].iter()
+    .fold(hash, |h, &b| h.wrapping_mul(37).wrapping_add(b as u32))
+}
@@ -16,17 +16,46 @@
 // Authors: zadig <thomas chr(0x40) bailleux.me>
 //          jasa <jan.starke (0x40) t-systems.com>
 
-use std;
 
-pub(crate) struct Header {
+/// The header of a Prefetch file.
+///
+/// See `Prefetch::read_header` to read only the header of a file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Header {
   pub(crate) version: super::prefetch::FormatVersion,
   pub(crate) size: usize,
-  pub(crate) name: std::string::String,
+  pub(crate) name: super::utf16::Utf16String,
   pub(crate) hash: u32
 }
 
 impl Header {
 
+  /// Returns the version of the Prefetch file.
+  pub fn version(&self) -> super::prefetch::FormatVersion {
+    self.version
+  }
+
+  /// Returns the size of the Prefetch file.
+  pub fn size(&self) -> usize {
+    self.size
+  }
+
+  /// Returns the name of the executable.
+  pub fn name(&self) -> &str {
+    self.name.as_str()
+  }
+
+  /// Returns the name of the executable, as stored in the file.
+  pub fn name_utf16(&self) -> &super::utf16::Utf16String {
+    &self.name
+  }
+
+  /// Returns the prefetch hash of the executable.
+  pub fn hash(&self) -> u32 {
+    self.hash
+  }
+
   pub(crate) fn new(content: &[u8]) -> super::Result<(Header,
   Box<dyn super::parser::Parser>)> {
     use super::util::FromSlice;
@@ -36,25 +65,19 @@ impl Header {
     } else {
       let (version, parser) = super::prefetch::FormatVersion::new(
         u32::from_slice(&content[0x0 .. 0x4]))?;
-      if &content[0x4 .. 0x8] != &super::constants::HEADER_CONSTANT_FIELD {
+      if content[0x4 .. 0x8] != super::constants::HEADER_CONSTANT_FIELD {
         result = Err(super::error::Error::NotPrefetchFile);
       } else {
         let size = usize::from_slice(&content[0xc .. 0x10]);
-        let mut name =std::string::String::with_capacity(
-            super::constants::MAX_FILENAME_LENGTH);
-        let mut i = 0;
         let limit = (super::constants::MAX_FILENAME_LENGTH + 1) * 2;
-        let name_slice = &content[0x10 .. 0x4c];
-        while i < limit && name_slice[i] != 0 {
-          name.push(name_slice[i] as char);
-          i += 2;
-        }
+        let name = super::utf16::Utf16String::from_bytes(
+          &content[0x10 .. 0x10 + limit]);
         let hash = u32::from_slice(&content[0x4c .. 0x50]);
         result = Ok((Header {
-          version: version,
-          size: size,
-          name: name,
-          hash: hash
+          version,
+          size,
+          name,
+          hash
         }, parser));
       }
     }
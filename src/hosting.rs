@@ -0,0 +1,217 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+//! Command lines of hosting processes.
+//!
+//! For hosting executables, such as `SVCHOST.EXE` or `RUNDLL32.EXE`, Windows
+//! mixes the command line of the process into the path hash: each command
+//! line gets its own Prefetch file. `match_command_line` finds which of a list
+//! of candidate command lines produces the hash of a file.
+//!
+//! The way the command line is mixed in is assumed, not checked against files
+//! written by Windows: the hash is taken over the device path directly
+//! followed by the command line, both upper-cased like paths. A candidate
+//! which does not match is therefore not ruled out. The candidates must be
+//! given as Windows saw them, executable included, such as
+//! `C:\WINDOWS\SYSTEM32\SVCHOST.EXE -k netsvcs`. `svchost_command_lines`
+//! returns the command lines of the common svchost groups.
+//!
+//! # Example
+//!
+//! ```
+//! use libprefetch::{FormatVersion, Prefetch};
+//! use libprefetch::hosting;
+//! use libprefetch::metric::MetricEntry;
+//! use libprefetch::writer::Writer;
+//!
+//! // A file hashed like `hosting::hash` does for `-k netsvcs`
+//! let path = r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\SVCHOST.EXE";
+//! let data = Writer::new(FormatVersion::Windows10, "SVCHOST.EXE".into(),
+//!     0x6ea48338)
+//!   .metrics(vec![MetricEntry::new(0, path.into(), 0, 0)])
+//!   .to_bytes();
+//! let prefetch = Prefetch::new(&data[..]).unwrap();
+//!
+//! assert!(hosting::is_hosting_executable(prefetch.name()));
+//! let candidates = hosting::svchost_command_lines(r"C:\WINDOWS");
+//! let m = hosting::match_command_line(&prefetch, &candidates).unwrap();
+//! assert_eq!(path, m.path());
+//! assert_eq!(r"C:\WINDOWS\SYSTEM32\SVCHOST.EXE -k netsvcs", m.command_line());
+//! ```
+
+use std;
+use super::hash::HashAlgorithm;
+
+/// Executables whose Prefetch hash includes the command line.
+pub const HOSTING_EXECUTABLES: &[&str] = &[
+  "SVCHOST.EXE",
+  "DLLHOST.EXE",
+  "RUNDLL32.EXE",
+  "MMC.EXE",
+  "BACKGROUNDTASKHOST.EXE"
+];
+
+/// Common service groups of svchost, passed with `-k`.
+pub const SVCHOST_GROUPS: &[&str] = &[
+  "AarSvcGroup",
+  "appmodel",
+  "AppReadiness",
+  "AssignedAccessManagerSvc",
+  "autoTimeSvc",
+  "AxInstSVGroup",
+  "BcastDVRUserService",
+  "BthAppGroup",
+  "camera",
+  "CameraMonitor",
+  "ClipboardSvcGroup",
+  "CloudIdServiceGroup",
+  "DcomLaunch",
+  "defragsvc",
+  "DevicesFlow",
+  "diagnostics",
+  "DialogBlockingService",
+  "GraphicsPerfSvcGroup",
+  "ICService",
+  "imgsvc",
+  "KpsSvcGroup",
+  "LocalService",
+  "LocalServiceAndNoImpersonation",
+  "LocalServiceNetworkRestricted",
+  "LocalServiceNoNetwork",
+  "LocalServiceNoNetworkFirewall",
+  "LocalServicePeerNet",
+  "LocalSystemNetworkRestricted",
+  "LxssManagerUser",
+  "McpManagementServiceGroup",
+  "netsvcs",
+  "NetworkService",
+  "NetworkServiceAndNoImpersonation",
+  "NetworkServiceNetworkRestricted",
+  "P9RdrService",
+  "PeerDist",
+  "print",
+  "PrintWorkflow",
+  "rdxgroup",
+  "RPCSS",
+  "sdrsvc",
+  "secsvcs",
+  "smbsvcs",
+  "smphost",
+  "swprv",
+  "termsvcs",
+  "UdkSvcGroup",
+  "UnistackSvcGroup",
+  "utcsvc",
+  "WbioSvcGroup",
+  "WepHostSvcGroup",
+  "WerSvcGroup",
+  "wsappx",
+  "WSearch",
+  "wusvcs"
+];
+
+/// A candidate command line which produces the hash of a Prefetch file.
+#[derive(Debug, Clone)]
+pub struct CommandLineMatch<'c> {
+  path: std::string::String,
+  command_line: &'c str,
+  algorithm: HashAlgorithm
+}
+
+impl<'c> CommandLineMatch<'c> {
+
+  /// Returns the NT device path of the executable.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Returns the matching command line.
+  pub fn command_line(&self) -> &'c str {
+    self.command_line
+  }
+
+  /// Returns the algorithm which produced the hash.
+  pub fn algorithm(&self) -> HashAlgorithm {
+    self.algorithm
+  }
+}
+
+/// Returns whether the Prefetch hash of the executable `name` includes the
+/// command line, compared without case.
+pub fn is_hosting_executable(name: &str) -> bool {
+  HOSTING_EXECUTABLES.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+/// Returns the hash of the executable at `path`, an NT device path, started
+/// with `command_line`, under the assumed construction of the module: the
+/// path directly followed by the command line. Windows may put them together
+/// differently.
+pub fn hash(algorithm: HashAlgorithm, path: &str, command_line: &str) -> u32 {
+  algorithm.hash(&format!("{}{}", path, command_line))
+}
+
+/// Returns the svchost command lines of the `SVCHOST_GROUPS`, for the
+/// Windows directory `system_root`, such as `C:\WINDOWS`.
+///
+/// Each group comes with and without `-p`, which Windows 10 adds to most of
+/// them.
+pub fn svchost_command_lines(system_root: &str)
+    -> std::vec::Vec<std::string::String> {
+  let system_root = system_root.trim_end_matches('\\');
+  SVCHOST_GROUPS.iter()
+    .flat_map(|group| vec![
+      format!(r"{}\SYSTEM32\SVCHOST.EXE -k {}", system_root, group),
+      format!(r"{}\SYSTEM32\SVCHOST.EXE -k {} -p", system_root, group)
+    ])
+    .collect()
+}
+
+/// Returns the first of `candidates` which, with the path of the executable,
+/// produces the hash of `prefetch`.
+///
+/// The paths tried are the metric filenames which have the name of the
+/// executable, see `Prefetch::executable_path`. `None` means that no
+/// candidate matches, or that the path of the executable is unknown.
+pub fn match_command_line<'c, S>(prefetch: &super::Prefetch,
+    candidates: &'c [S]) -> Option<CommandLineMatch<'c>>
+    where S: AsRef<str> {
+  let executable = prefetch.executable_path()?;
+  let mut paths: std::vec::Vec<&str> = executable.candidates().iter()
+    .map(|c| c.as_str())
+    .collect();
+  if !paths.contains(&executable.path()) {
+    paths.push(executable.path());
+  }
+
+  let algorithms = HashAlgorithm::candidates(prefetch.version());
+  for path in paths {
+    for candidate in candidates {
+      let command_line = candidate.as_ref();
+      for &algorithm in &algorithms {
+        if hash(algorithm, path, command_line) == prefetch.hash() {
+          return Some(CommandLineMatch {
+            path: path.to_string(),
+            command_line,
+            algorithm
+          });
+        }
+      }
+    }
+  }
+  None
+}
@@ -29,14 +29,13 @@ pub struct MetricIterator<'p> {
 impl<'p> MetricIterator<'p> {
 
   pub(crate) fn new(parser_result: &'p super::parser::ParserResult)
-      -> super::Result<MetricIterator> {
-    if parser_result.metrics.is_ok() {
-      Ok(MetricIterator {
-        parser_result: parser_result,
+      -> super::Result<MetricIterator<'p>> {
+    match *parser_result.metrics() {
+      Ok(_) => Ok(MetricIterator {
+        parser_result,
         curr: 0
-      })
-    } else {
-      Err(super::error::Error::NotImplemented)
+      }),
+      Err(ref e) => Err(e.duplicate())
     }
   }
 }
@@ -45,7 +44,7 @@ impl<'p> Iterator for MetricIterator<'p> {
   type Item = &'p super::metric::MetricEntry;
 
   fn next(&mut self) -> Option<Self::Item> {
-    match self.parser_result.metrics {
+    match *self.parser_result.metrics() {
       Ok(ref v) => {
         if self.curr < v.len() {
           self.curr += 1;
@@ -71,14 +70,13 @@ pub struct TraceIterator<'p> {
 impl<'p> TraceIterator<'p> {
 
   pub(crate) fn new(parser_result: &'p super::parser::ParserResult)
-      -> super::Result<TraceIterator> {
-    if parser_result.trace.is_ok() {
-      Ok(TraceIterator {
-        parser_result: parser_result,
+      -> super::Result<TraceIterator<'p>> {
+    match *parser_result.trace() {
+      Ok(_) => Ok(TraceIterator {
+        parser_result,
         curr: 0
-      })
-    } else {
-      Err(super::error::Error::NotImplemented)
+      }),
+      Err(ref e) => Err(e.duplicate())
     }
   }
 }
@@ -87,7 +85,7 @@ impl<'p> Iterator for TraceIterator<'p> {
   type Item = &'p super::trace::TraceEntry;
 
   fn next(&mut self) -> Option<Self::Item> {
-    match self.parser_result.trace {
+    match *self.parser_result.trace() {
       Ok(ref v) => {
         if self.curr < v.len() {
           self.curr += 1;
@@ -112,14 +110,13 @@ pub struct VolumeIterator<'p> {
 impl<'p> VolumeIterator<'p> {
 
   pub(crate) fn new(parser_result: &'p super::parser::ParserResult)
-      -> super::Result<VolumeIterator> {
-    if parser_result.volumes.is_ok() {
-      Ok(VolumeIterator {
-        parser_result: parser_result,
+      -> super::Result<VolumeIterator<'p>> {
+    match *parser_result.volumes() {
+      Ok(_) => Ok(VolumeIterator {
+        parser_result,
         curr: 0
-    })
-    } else {
-      Err(super::error::Error::NotImplemented)
+      }),
+      Err(ref e) => Err(e.duplicate())
     }
   }
 }
@@ -128,7 +125,7 @@ impl<'p> Iterator for VolumeIterator<'p> {
   type Item = &'p super::volume::VolumeEntry;
 
   fn next(&mut self) -> Option<Self::Item> {
-    match self.parser_result.volumes {
+    match *self.parser_result.volumes() {
       Ok(ref v) => {
         if self.curr < v.len() {
           self.curr += 1;
@@ -154,9 +151,9 @@ pub struct DirectoryIterator<'ve> {
 impl<'ve> DirectoryIterator<'ve> {
 
   pub(crate) fn new(volume_entry: &'ve super::volume::VolumeEntry)
-      -> super::Result<DirectoryIterator> {
+      -> super::Result<DirectoryIterator<'ve>> {
     Ok(DirectoryIterator {
-      volume_entry: volume_entry,
+      volume_entry,
       curr: 0
     })
   }
@@ -168,7 +165,257 @@ impl<'ve> Iterator for DirectoryIterator<'ve> {
   fn next(&mut self) -> Option<Self::Item> {
     if self.curr < self.volume_entry.directories.len() {
       self.curr += 1;
-      Some(&self.volume_entry.directories[self.curr - 1])
+      Some(self.volume_entry.directories[self.curr - 1].as_str())
+    } else {
+      None
+    }
+  }
+}
+
+/// Iterator for NTFS file references of a volume.
+///
+/// This iterates through all `FileReference`.
+pub struct FileReferenceIterator<'ve> {
+  volume_entry: &'ve super::volume::VolumeEntry,
+  curr: usize
+}
+
+impl<'ve> FileReferenceIterator<'ve> {
+
+  pub(crate) fn new(volume_entry: &'ve super::volume::VolumeEntry)
+      -> super::Result<FileReferenceIterator<'ve>> {
+    if volume_entry.file_references.is_some() {
+      Ok(FileReferenceIterator {
+        volume_entry,
+        curr: 0
+      })
+    } else {
+      Err(super::error::Error::NotImplemented)
+    }
+  }
+}
+
+impl<'ve> Iterator for FileReferenceIterator<'ve> {
+  type Item = &'ve super::volume::FileReference;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    match self.volume_entry.file_references {
+      Some(ref v) => {
+        if self.curr < v.len() {
+          self.curr += 1;
+          Some(&v[self.curr - 1])
+        } else {
+          None
+        }
+      },
+      None => None
+    }
+  }
+}
+
+/// Iterator for the file metrics of a `PrefetchRef`.
+///
+/// This decodes each `MetricEntryRef` on demand.
+pub struct MetricRefIterator<'a> {
+  layout: &'static super::metric::MetricLayout,
+  entries: &'a [u8],
+  names: &'a [u8],
+  curr: usize
+}
+
+impl<'a> MetricRefIterator<'a> {
+
+  pub(crate) fn new(layout: &'static super::metric::MetricLayout,
+      content: &'a [u8]) -> super::Result<MetricRefIterator<'a>> {
+    let (entries, names) = layout.sections(content)?;
+    Ok(MetricRefIterator {
+      layout,
+      entries,
+      names,
+      curr: 0
+    })
+  }
+}
+
+impl<'a> Iterator for MetricRefIterator<'a> {
+  type Item = super::Result<super::metric::MetricEntryRef<'a>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let entry_size = self.layout.entry_size;
+    let offset = self.curr * entry_size;
+    if offset < self.entries.len() {
+      self.curr += 1;
+      Some(self.layout.parse_entry(self.curr - 1,
+        &self.entries[offset .. offset + entry_size], self.names))
+    } else {
+      None
+    }
+  }
+}
+
+/// Iterator for the trace chain of a `PrefetchRef`.
+///
+/// This decodes each `TraceEntry` on demand.
+pub struct TraceRefIterator<'a> {
+  layout: super::trace::TraceLayout,
+  entries: &'a [u8],
+  first: usize,
+  curr: usize
+}
+
+impl<'a> TraceRefIterator<'a> {
+
+  pub(crate) fn new(layout: super::trace::TraceLayout, content: &'a [u8])
+      -> super::Result<TraceRefIterator<'a>> {
+    Ok(TraceRefIterator::from_entries(layout, layout.section(content)?, 0))
+  }
+
+  /// Iterates over `entries`, the first of which has the ID `first`.
+  pub(crate) fn from_entries(layout: super::trace::TraceLayout,
+      entries: &'a [u8], first: usize) -> TraceRefIterator<'a> {
+    TraceRefIterator {
+      layout,
+      entries,
+      first,
+      curr: 0
+    }
+  }
+}
+
+impl<'a> Iterator for TraceRefIterator<'a> {
+  type Item = super::trace::TraceEntry;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let entry_size = self.layout.entry_size();
+    let offset = self.curr * entry_size;
+    if offset < self.entries.len() {
+      self.curr += 1;
+      Some(self.layout.parse_entry(self.first + self.curr - 1,
+        &self.entries[offset .. offset + entry_size]))
+    } else {
+      None
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let n = self.entries.len() / self.layout.entry_size() - self.curr;
+    (n, Some(n))
+  }
+}
+
+/// Iterator for the volumes of a `PrefetchRef`.
+///
+/// This decodes each `VolumeEntryRef` on demand.
+pub struct VolumeRefIterator<'a> {
+  layout: &'static super::volume::VolumeLayout,
+  section: &'a [u8],
+  entries: &'a [u8],
+  curr: usize
+}
+
+impl<'a> VolumeRefIterator<'a> {
+
+  pub(crate) fn new(layout: &'static super::volume::VolumeLayout,
+      content: &'a [u8]) -> super::Result<VolumeRefIterator<'a>> {
+    let (section, entries) = layout.sections(content)?;
+    Ok(VolumeRefIterator {
+      layout,
+      section,
+      entries,
+      curr: 0
+    })
+  }
+}
+
+impl<'a> Iterator for VolumeRefIterator<'a> {
+  type Item = super::Result<super::volume::VolumeEntryRef<'a>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let entry_size = self.layout.entry_size;
+    let offset = self.curr * entry_size;
+    if offset < self.entries.len() {
+      self.curr += 1;
+      Some(self.layout.parse_entry(self.curr - 1,
+        &self.entries[offset .. offset + entry_size], self.section))
+    } else {
+      None
+    }
+  }
+}
+
+/// Iterator for the directories of a `VolumeEntryRef`.
+///
+/// The iteration stops after the first error.
+pub struct DirectoryRefIterator<'a> {
+  section: &'a [u8],
+  offset: usize,
+  remaining: usize
+}
+
+impl<'a> DirectoryRefIterator<'a> {
+
+  pub(crate) fn new(section: &'a [u8], offset: usize, n: usize)
+      -> DirectoryRefIterator<'a> {
+    DirectoryRefIterator {
+      section,
+      offset,
+      remaining: n
+    }
+  }
+}
+
+impl<'a> Iterator for DirectoryRefIterator<'a> {
+  type Item = super::Result<super::utf16::Utf16Str<'a>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    use super::error::Section;
+
+    if self.remaining == 0 {
+      return None;
+    }
+    self.remaining -= 1;
+    let directory = super::util::read::<usize>(self.section,
+      Section::Directories, self.offset, 2)
+      .and_then(|size| {
+        let start = self.offset + 2;
+        let directory = super::util::fetch_unicode_string(self.section,
+          Section::Directories, start, size)?;
+        // Each string is NUL terminated
+        self.offset = start + size * 2 + 2;
+        Ok(directory)
+      });
+    if directory.is_err() {
+      self.remaining = 0;
+    }
+    Some(directory)
+  }
+}
+
+/// Iterator for the NTFS file references of a `VolumeEntryRef`.
+pub struct FileReferenceRefIterator<'a> {
+  entries: &'a [u8],
+  curr: usize
+}
+
+impl<'a> FileReferenceRefIterator<'a> {
+
+  pub(crate) fn new(entries: &'a [u8]) -> FileReferenceRefIterator<'a> {
+    FileReferenceRefIterator {
+      entries,
+      curr: 0
+    }
+  }
+}
+
+impl<'a> Iterator for FileReferenceRefIterator<'a> {
+  type Item = super::volume::FileReference;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let offset = self.curr * 8;
+    if offset < self.entries.len() {
+      self.curr += 1;
+      Some(super::volume::parse_file_reference(
+        &self.entries[offset .. offset + 8]))
     } else {
       None
     }
@@ -24,13 +24,13 @@
 //!   * Windows Vista
 //!   * Windows 7
 //!   * Windows 8/8.1
-//!
-//! `libprefetch` **partially supports** Windows 10.
+//!   * Windows 10
+//!   * Windows 11
 //!
 //! Features:
 //!   * Parser and validator
 //!   * Auto detects version of Windows
-//!   * Provides the last execution time and the execution counter
+//!   * Provides the last execution times and the execution counter
 //!   * Provides metric information about loaded files (like dll etc) **if available**, such as :
 //!     * filename
 //!     * start time
@@ -38,15 +38,38 @@
 //!     * average duration
 //!     * NTFS MFT entry
 //!     * NTFS sequence numer
-//!   * Provides the trace chains (**unavailable for Windows 10**)
+//!   * Provides the trace chains
 //!   * Provides all pieces of information about the volumes:
 //!     * device path
 //!     * creation time
 //!     * serial number
 //!     * list of directories
+//!     * NTFS file references
+//!   * Zero-copy view over the file data with `PrefetchRef`
+//!   * Writes Prefetch files of every version with `writer::Writer`
+//!   * Compresses and decompresses `MAM` files, see the `compression` module
+//!   * Computes and verifies the path hashes, see the `hash` module
+//!   * Resolves the full path of the executable with
+//!     `Prefetch::executable_path`
+//!   * Builds the directory tree of each volume, see the `directory` module
+//!   * Finds the command line of hosting processes such as svchost, see the
+//!     `hosting` module
+//!   * Flags suspicious executions with configurable heuristics, see the
+//!     `analysis` module
 //!
 //! This library will be used in a global forensic computing library very soon.
 //!
+//! ## Cargo features
+//!
+//!   * `log`: logs the parsing events (see `Prefetch::with_diagnostics`) with
+//!     the `log` crate.
+//!   * `chrono`: converts a `FileTime` to a `chrono::DateTime`.
+//!   * `time`: converts a `FileTime` to a `time::OffsetDateTime`.
+//!   * `serde`: serializes and deserializes a parsed `Prefetch`, see the
+//!     `serialization` module.
+//!   * `registry`: maps device paths to drive letters with the
+//!     `MountedDevices` key of a SYSTEM hive, see the `registry` module.
+//!
 //! ## Example
 //!
 //! ```rust
@@ -60,15 +83,15 @@
 //! println!("Executable {} launched {} times. The last time was: {}",
 //!   prefetch.name(),
 //!   prefetch.execution_counter(),
-//!   prefetch.last_execution_time() // TODO: format the FILETIME here
+//!   prefetch.last_execution_time()
 //! );
 //!
 //! // Iterates over all loaded DLL etc for the prefetch file
 //! println!(" ===== File metrics ===== ");
 //! for metric in prefetch.metrics().unwrap() {
 //!   println!("#{}: {}", metric.id(), metric.filename());
-//!   println!("    start time: {}", metric.start_time().unwrap());
-//!   println!("    duration: {}", metric.duration().unwrap());
+//!   println!("    trace entries: {} from #{}", metric.trace_entry_count(),
+//!     metric.trace_chain_index());
 //!   println!(" ------------------------------- ");
 //! }
 //!
@@ -96,19 +119,39 @@
 //!
 //! `libprefetch` seems to work for rust 1.9 and greater.
 mod prefetch;
+mod prefetch_ref;
 mod parser;
 mod error;
 mod constants;
 mod header;
 mod util;
+mod filetime;
+pub mod analysis;
+pub mod compression;
+pub mod directory;
+pub mod executable;
+pub mod hash;
+pub mod hosting;
 pub mod iterator;
 pub mod metric;
 pub mod trace;
 pub mod volume;
+pub mod utf16;
+pub mod diagnostics;
+pub mod writer;
+#[cfg(feature = "serde")]
+pub mod serialization;
+#[cfg(feature = "registry")]
+mod regf;
+#[cfg(feature = "registry")]
+pub mod registry;
 
 pub(crate) use error::Result;
 pub use prefetch::{FormatVersion, Prefetch};
-pub use error::Error;
+pub use prefetch_ref::PrefetchRef;
+pub use header::Header;
+pub use filetime::FileTime;
+pub use error::{Error, Section};
 
 #[cfg(test)]
 mod tests {
@@ -117,16 +160,22 @@ mod tests {
     fn prelude() -> prefetch::Prefetch {
       let f =
         std::fs::File::open("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
-      let p = prefetch::Prefetch::new(f).unwrap();
-
-      p
+      prefetch::Prefetch::new(f).unwrap()
     }
 
     #[test]
     fn header() {
       let p = prelude();
-      assert_eq!(129453035816965472, p.last_execution_time());
+      assert_eq!(129453035816965472, p.last_execution_time().value());
       assert_eq!(38, p.execution_counter());
+      assert_eq!(&[FileTime::new(129453035816965472)], p.last_execution_times());
+
+      for version in &[FormatVersion::WindowsXp2003, FormatVersion::WindowsVista7] {
+        let data = writer::Writer::new(*version, "CALC.EXE".into(), 0).to_bytes();
+        let p = Prefetch::new(&data[..]).unwrap();
+        assert!(p.last_execution_times().is_empty());
+        assert!(p.last_execution_time().is_zero());
+      }
     }
 
     #[test]
@@ -134,6 +183,9 @@ mod tests {
       let p = prelude();
       for metric in p.metrics().unwrap() {
         println!("metric={:?}", metric);
+        let trace = metric.trace_entries(&p).unwrap();
+        assert_eq!(metric.trace_entry_count(), trace.len());
+        assert_eq!(None, trace.last().unwrap().next_entry_id());
       }
     }
 
@@ -142,6 +194,7 @@ mod tests {
       let p = prelude();
       for trace in p.trace().unwrap() {
         println!("trace={:?}", trace);
+        assert_eq!(trace::TraceLayout::Chained, trace.layout());
       }
     }
 
@@ -150,6 +203,7 @@ mod tests {
       let p = prelude();
       for volume in p.volumes().unwrap() {
         println!("volume={:?}", volume);
+        assert!(volume.file_references().is_err());
       }
     }
 
@@ -163,6 +217,1016 @@ mod tests {
       }
     }
 
+    #[test]
+    fn windows10() {
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      assert_eq!("NOTEPAD.EXE", p.name());
+      assert_eq!(0xd8414f97, p.hash());
+      assert_eq!(0x01d4a3c2e8f1b200, p.last_execution_time().value());
+      assert_eq!(6, p.execution_counter());
+      assert_eq!(6, p.last_execution_times().len());
+
+      let metrics = p.metrics().unwrap().collect::<Vec<_>>();
+      assert_eq!(6, metrics.len());
+      assert_eq!("\\DEVICE\\HARDDISKVOLUME2\\WINDOWS\\SYSTEM32\\NTDLL.DLL",
+        metrics[0].filename());
+      assert_eq!(Some(0x1f3a), metrics[0].mft_entry_index());
+      assert_eq!(Some(1), metrics[0].sequence_number());
+      assert_eq!(0x0202, metrics[0].flags());
+      assert_eq!(3100, metrics[1].trace_chain_index());
+      assert_eq!(2200, metrics[1].trace_entries(&p).unwrap().len());
+
+      assert_eq!(9252, p.trace().unwrap().count());
+      assert!(p.trace().unwrap().all(|t| t.next_entry_id().is_none()
+        && t.layout() == trace::TraceLayout::Flat));
+      let first = p.trace().unwrap().next().unwrap();
+      assert_eq!(5, first.load_count());
+      assert_eq!(trace::TRACE_FLAG_EXECUTABLE | trace::TRACE_FLAG_RESOURCE,
+        first.flags());
+
+      let volumes = p.volumes().unwrap().collect::<Vec<_>>();
+      assert_eq!(1, volumes.len());
+      assert_eq!("\\DEVICE\\HARDDISKVOLUME2", volumes[0].device_path());
+      assert_eq!(0x4a3b2c1d, volumes[0].serial_number());
+      assert_eq!(6, volumes[0].directories().unwrap().count());
+      let references = volumes[0].file_references().unwrap().collect::<Vec<_>>();
+      assert_eq!(5, references.len());
+      assert_eq!(0x5c01, references[3].mft_entry_index());
+      assert_eq!(2, references[3].sequence_number());
+    }
+
+    #[test]
+    fn windows11() {
+      let p = Prefetch::from_path("assets/CMD.EXE-0BD30981.pf").unwrap();
+      assert_eq!("Windows 11", format!("{}", p.version()));
+      assert_eq!("CMD.EXE", p.name());
+      assert_eq!(42, p.execution_counter());
+      assert_eq!(vec![0x01d8f1e2d3c4b5a0, 0x01d8f0b1a2938470, 0x01d8e0a1b2c3d4e0],
+        p.last_execution_times().iter().map(|t| t.value()).collect::<Vec<_>>());
+      assert_eq!(4, p.metrics().unwrap().count());
+      assert_eq!(1825, p.trace().unwrap().count());
+
+      let volume = p.volumes().unwrap().next().unwrap();
+      assert_eq!("\\VOLUME{01d7b2a4c1e3f500-7e3f9a21}", volume.device_path());
+      assert_eq!(0x01d7b2a4c1e3f500, volume.creation_time().value());
+    }
+
+    #[test]
+    fn compressed() {
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97-MAM.pf").unwrap();
+      let u = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      assert_eq!(u.size(), p.size());
+      assert_eq!(u.last_execution_times(), p.last_execution_times());
+      assert_eq!(u.trace().unwrap().count(), p.trace().unwrap().count());
+      assert!(u.metrics().unwrap().zip(p.metrics().unwrap())
+        .all(|(a, b)| a.filename() == b.filename()));
+
+      let mut data = std::fs::read("assets/NOTEPAD.EXE-D8414F97-MAM.pf").unwrap();
+      data[4] = data[4].wrapping_add(1);
+      assert!(Prefetch::new(&data[..]).is_err());
+    }
+
+    #[test]
+    fn compressed_checksum() {
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97-MAM-CRC.pf")
+        .unwrap();
+      assert_eq!(9252, p.trace().unwrap().count());
+
+      let mut data =
+        std::fs::read("assets/NOTEPAD.EXE-D8414F97-MAM-CRC.pf").unwrap();
+      data[0x100] ^= 0x01;
+      match Prefetch::new(&data[..]) {
+        Err(Error::ChecksumMismatch(0x28c5747b, _)) => (),
+        _ => panic!("the checksum mismatch is not detected")
+      }
+
+      data[3] = 0x83;
+      match Prefetch::new(&data[..]) {
+        Err(Error::UnsupportedCompression(0x83)) => (),
+        _ => panic!("the compression format is not checked")
+      }
+    }
+
+    fn walk(data: &[u8]) {
+      if let Ok(p) = Prefetch::new(data) {
+        let _ = p.metrics().map(|m| m.count());
+        let _ = p.trace().map(|t| t.count());
+        if let Ok(volumes) = p.volumes() {
+          for v in volumes {
+            let _ = v.directories().map(|d| d.count());
+            let _ = v.file_references().map(|r| r.count());
+          }
+        }
+      }
+      if let Ok(p) = PrefetchRef::new(data) {
+        let _ = p.metrics().map(|m| m.count());
+        let _ = p.trace().map(|t| t.count());
+        if let Ok(volumes) = p.volumes() {
+          for v in volumes.flatten() {
+            let _ = v.directories().count();
+            let _ = v.file_references().map(|r| r.count());
+          }
+        }
+      }
+    }
+
+    #[test]
+    fn malformed() {
+      for path in &["assets/WUAUCLT.EXE-399A8E72.pf",
+          "assets/NOTEPAD.EXE-D8414F97.pf",
+          "assets/NOTEPAD.EXE-D8414F97-MAM.pf"] {
+        let data = std::fs::read(path).unwrap();
+        for length in (0 .. data.len()).step_by(61) {
+          walk(&data[.. length]);
+        }
+        for offset in 0x54 .. 0x78 {
+          let mut corrupted = data.clone();
+          corrupted[offset] = 0xff;
+          walk(&corrupted);
+        }
+      }
+
+      let data = std::fs::read("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      match Prefetch::new(&data[.. 0x200]).unwrap().trace() {
+        Err(Error::OutOfBounds(Section::TraceChain, 0x1f4, 0x12120)) => (),
+        _ => panic!("the truncated trace chain is not reported")
+      }
+      assert!(Prefetch::new(&b"MAM"[..]).is_err());
+    }
+
+    #[test]
+    fn utf16() {
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let metric = p.metrics().unwrap().last().unwrap();
+      assert_eq!("\\DEVICE\\HARDDISKVOLUME2\\USERS\\ANALYST\\DOCUMENTS\\ОТЧЁТ.TXT",
+        metric.filename());
+      assert_eq!(0x401, metric.filename_utf16().units()[51]);
+
+      // An unpaired surrogate followed by a pair (U+1F600).
+      let s = utf16::Utf16String::new(vec![0x41, 0xd800, 0x42, 0xd83d, 0xde00]);
+      assert_eq!("A\u{fffd}B\u{1f600}", s.as_str());
+      assert!(!s.is_valid());
+      match s.to_string_strict() {
+        Err(Error::InvalidUtf16(1)) => (),
+        _ => panic!("the unpaired surrogate is not reported")
+      }
+      let s = utf16::Utf16String::from_bytes(&[0x3d, 0xd8, 0x00, 0xde, 0, 0, 0x41, 0]);
+      assert_eq!("\u{1f600}", s.to_string_strict().unwrap());
+    }
+
+    #[test]
+    fn diagnostics() {
+      let file = std::fs::File::open("assets/NOTEPAD.EXE-D8414F97-MAM-CRC.pf")
+        .unwrap();
+      let mut events = Vec::new();
+      Prefetch::with_diagnostics(file, |e: &diagnostics::Event| {
+        events.push(e.to_string());
+      }).unwrap();
+      assert_eq!(vec![
+        "decompressed 23207 bytes into 75876 bytes, checksum verified",
+        "format version: Windows 10",
+        "file metrics: 192 bytes at offset 0x134",
+        "trace chain: 74016 bytes at offset 0x1f4",
+        "filename strings: 670 bytes at offset 0x12314",
+        "volumes information: 684 bytes at offset 0x125b8"
+      ], events);
+
+      let data = std::fs::read("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let mut skipped = Vec::new();
+      Prefetch::with_diagnostics(&data[.. 0x12600], |e: &diagnostics::Event| {
+        match *e {
+          diagnostics::Event::SizeMismatch(75876, 0x12600) => (),
+          diagnostics::Event::SectionSkipped(s, _) => skipped.push(s),
+          _ => ()
+        }
+      }).unwrap();
+      assert_eq!(vec![Section::Volumes], skipped);
+    }
+
+    #[test]
+    fn borrowed() {
+      for path in &["assets/WUAUCLT.EXE-399A8E72.pf",
+          "assets/NOTEPAD.EXE-D8414F97.pf",
+          "assets/NOTEPAD.EXE-D8414F97-MAM-CRC.pf",
+          "assets/CMD.EXE-0BD30981.pf"] {
+        let data = std::fs::read(path).unwrap();
+        let owned = Prefetch::new(&data[..]).unwrap();
+        let borrowed = PrefetchRef::new(&data).unwrap();
+        assert_eq!(!path.contains("MAM"), borrowed.is_borrowed());
+        assert_eq!(owned.name(), borrowed.name().to_string_lossy());
+        assert_eq!(owned.hash(), borrowed.hash());
+        assert_eq!(owned.last_execution_times(),
+          &borrowed.last_execution_times().unwrap()[..]);
+        assert_eq!(owned.execution_counter(),
+          borrowed.execution_counter().unwrap());
+
+        for (m, r) in owned.metrics().unwrap().zip(borrowed.metrics().unwrap()) {
+          let r = r.unwrap();
+          assert_eq!(m.filename_utf16(), &r.filename().to_utf16_string());
+          assert_eq!(m.mft_entry_index(), r.mft_entry_index());
+          assert_eq!(m.trace_entries(&owned).unwrap().len(),
+            borrowed.trace_entries(&r).unwrap().count());
+        }
+        assert_eq!(owned.trace().unwrap().count(),
+          borrowed.trace().unwrap().count());
+        assert_eq!(owned.trace().unwrap().map(|t| t.load_count()).sum::<usize>(),
+          borrowed.trace().unwrap().map(|t| t.load_count()).sum::<usize>());
+
+        for (v, r) in owned.volumes().unwrap().zip(borrowed.volumes().unwrap()) {
+          let r = r.unwrap();
+          assert_eq!(v.device_path(), r.device_path().to_string_lossy());
+          assert_eq!(v.serial_number(), r.serial_number());
+          assert_eq!(v.directories().unwrap().collect::<Vec<_>>(),
+            r.directories().map(|d| d.unwrap().to_string_lossy())
+              .collect::<Vec<_>>());
+          assert_eq!(v.file_references().ok().map(|f| f.count()),
+            r.file_references().ok().map(|f| f.count()));
+        }
+      }
+    }
+
+    #[test]
+    fn lazy() {
+      let data = std::fs::read("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let header = Prefetch::read_header(&data[.. 0x54]).unwrap();
+      assert_eq!("NOTEPAD.EXE", header.name());
+      assert_eq!(0xd8414f97, header.hash());
+      assert_eq!(75876, header.size());
+      assert!(Prefetch::read_header(&data[.. 0x53]).is_err());
+
+      let file = std::fs::File::open("assets/NOTEPAD.EXE-D8414F97-MAM.pf")
+        .unwrap();
+      assert_eq!("NOTEPAD.EXE", Prefetch::read_header(file).unwrap().name());
+
+      // The sections are only parsed when needed, once.
+      let p = Prefetch::new(&data[.. 0x12600]).unwrap();
+      assert_eq!(6, p.execution_counter());
+      assert_eq!("NOTEPAD.EXE", p.header().name());
+      let first = p.metrics().unwrap().next().unwrap();
+      assert!(std::ptr::eq(first, p.metrics().unwrap().next().unwrap()));
+      assert!(p.volumes().is_err());
+    }
+
+    // Records how far the source was read.
+    struct Tracked {
+      inner: std::io::Cursor<Vec<u8>>,
+      end: u64
+    }
+
+    impl std::io::Read for Tracked {
+      fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.end = std::cmp::max(self.end, self.inner.position());
+        Ok(n)
+      }
+    }
+
+    impl std::io::Seek for Tracked {
+      fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+      }
+    }
+
+    #[test]
+    fn reader_at() {
+      let data = std::fs::read("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let mut image = vec![0xaa; 0x1000];
+      image.extend_from_slice(&data);
+      image.extend_from_slice(&[0xaa; 0x1000]);
+      let mut src = Tracked { inner: std::io::Cursor::new(image), end: 0 };
+
+      let p = Prefetch::from_reader_at(&mut src, 0x1000).unwrap();
+      assert_eq!(0x1000 + data.len() as u64, src.end);
+      assert_eq!("NOTEPAD.EXE", p.name());
+      assert_eq!(6, p.execution_counter());
+      assert_eq!(6, p.metrics().unwrap().count());
+      assert_eq!(9252, p.trace().unwrap().count());
+      let volume = p.volumes().unwrap().next().unwrap();
+      assert_eq!(6, volume.directories().unwrap().count());
+      assert_eq!(5, volume.file_references().unwrap().count());
+
+      // Compressed files are read up to the end of their data, give or take
+      // a chunk, and the checksum only covers the file.
+      for path in &["assets/NOTEPAD.EXE-D8414F97-MAM.pf",
+          "assets/NOTEPAD.EXE-D8414F97-MAM-CRC.pf"] {
+        let compressed = std::fs::read(path).unwrap();
+        let mut image = vec![0; 0x200];
+        image.extend_from_slice(&compressed);
+        image.extend(vec![0xaa; 0x400000]);
+        let mut src = Tracked { inner: std::io::Cursor::new(image), end: 0 };
+        let p = Prefetch::from_reader_at(&mut src, 0x200).unwrap();
+        assert_eq!(9252, p.trace().unwrap().count());
+        assert!(src.end >= 0x200 + compressed.len() as u64);
+        assert!(src.end <= 0x200 + 2 * compressed.len() as u64 + 0x10000);
+      }
+
+      let mut image =
+        std::fs::read("assets/NOTEPAD.EXE-D8414F97-MAM-CRC.pf").unwrap();
+      image[0x8] ^= 0x01;
+      image.extend(vec![0xaa; 0x10000]);
+      match Prefetch::from_reader_at(std::io::Cursor::new(image), 0) {
+        Err(Error::ChecksumMismatch(0x28c5747a, _)) => (),
+        _ => panic!("the checksum mismatch is not detected")
+      }
+
+      let compressed = compression::compress_mam(&data, true);
+      let mut src = Tracked {
+        inner: std::io::Cursor::new(compressed[.. compressed.len() - 1].to_vec()),
+        end: 0
+      };
+      assert!(Prefetch::from_reader_at(&mut src, 0).is_err());
+    }
+
+    #[test]
+    fn filetime() {
+      let unset = FileTime::new(0);
+      assert!(unset.is_zero());
+      match unset.to_system_time() {
+        Err(Error::UnsetTime) => (),
+        _ => panic!("a zero FILETIME is converted")
+      }
+      assert!(unset.to_rfc3339().is_err());
+      assert_eq!("0x0", unset.to_string());
+
+      let epoch = FileTime::new(116444736000000000);
+      assert_eq!(std::time::UNIX_EPOCH, epoch.to_system_time().unwrap());
+      assert_eq!("1970-01-01T00:00:00Z", epoch.to_string());
+      assert_eq!("1601-01-01T00:00:00.0000001Z", FileTime::new(1).to_string());
+      assert!(FileTime::new(1).to_system_time().unwrap() < std::time::UNIX_EPOCH);
+      assert_eq!("2000-02-29T12:34:56.5Z",
+        FileTime::new(125963012965000000).to_rfc3339().unwrap());
+
+      let last = FileTime::new(265046774400 * 10_000_000 - 1);
+      assert_eq!("9999-12-31T23:59:59.9999999Z", last.to_rfc3339().unwrap());
+      match FileTime::new(last.value() + 1).to_rfc3339() {
+        Err(Error::TimeOutOfRange(t)) => assert_eq!(last.value() + 1, t),
+        _ => panic!("the year 10000 is formatted")
+      }
+
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      assert_eq!("2019-01-04T00:17:42.7723776Z",
+        p.last_execution_time().to_rfc3339().unwrap());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn filetime_chrono() {
+      let time = FileTime::new(125963012965000000).to_chrono().unwrap();
+      assert_eq!(951827696, time.timestamp());
+      assert_eq!(500_000_000, time.timestamp_subsec_nanos());
+      assert!(FileTime::new(0).to_chrono().is_err());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn filetime_time() {
+      let time = FileTime::new(125963012965000000).to_offset_date_time()
+        .unwrap();
+      assert_eq!(951827696, time.unix_timestamp());
+      assert_eq!(500_000_000, time.nanosecond());
+      assert!(FileTime::new(0).to_offset_date_time().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+      for path in &["assets/WUAUCLT.EXE-399A8E72.pf",
+          "assets/NOTEPAD.EXE-D8414F97.pf",
+          "assets/CMD.EXE-0BD30981.pf"] {
+        let p = Prefetch::from_path(path).unwrap();
+        let json = serde_json::to_value(&p).unwrap();
+        let loaded: Prefetch = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(json, serde_json::to_value(&loaded).unwrap());
+        let metric = loaded.metrics().unwrap().next().unwrap();
+        assert_eq!(metric.trace_entry_count(),
+          metric.trace_entries(&loaded).unwrap().len());
+      }
+
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let json = serde_json::to_value(&p).unwrap();
+      assert_eq!("Windows10", json["header"]["version"]);
+      assert_eq!("NOTEPAD.EXE", json["header"]["name"]["Str"]);
+      assert_eq!(0x01d4a3c2e8f1b200u64, json["last_execution_times"][0]);
+      assert_eq!("\\DEVICE\\HARDDISKVOLUME2", json["volumes"][0]["device_path"]["Str"]);
+      assert_eq!(0x01d3c5f9a6b7c8d0u64, json["volumes"][0]["creation_time"]);
+
+      let s = utf16::Utf16String::new(vec![0x41, 0xd800]);
+      let json = serde_json::to_value(&s).unwrap();
+      assert_eq!(serde_json::json!({"Units": [0x41, 0xd800]}), json);
+      assert_eq!(s, serde_json::from_value(json).unwrap());
+
+      let data = std::fs::read("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let p = Prefetch::new(&data[.. 0x12600]).unwrap();
+      let json = serde_json::to_value(&p).unwrap();
+      assert!(json["volumes"].is_null());
+      let loaded: Prefetch = serde_json::from_value(json).unwrap();
+      match loaded.volumes() {
+        Err(Error::SectionUnavailable(Section::Volumes)) => (),
+        _ => panic!("the unavailable section is not reported")
+      }
+      assert_eq!(6, loaded.metrics().unwrap().count());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_bincode() {
+      for path in &["assets/WUAUCLT.EXE-399A8E72.pf",
+          "assets/NOTEPAD.EXE-D8414F97.pf",
+          "assets/CMD.EXE-0BD30981.pf"] {
+        let p = Prefetch::from_path(path).unwrap();
+        let data = bincode::serialize(&p).unwrap();
+        let loaded: Prefetch = bincode::deserialize(&data).unwrap();
+        assert_eq!(data, bincode::serialize(&loaded).unwrap());
+        assert_eq!(p.name(), loaded.name());
+        assert_eq!(p.metrics().unwrap().count(), loaded.metrics().unwrap().count());
+      }
+
+      let s = utf16::Utf16String::new(vec![0x41, 0xd800]);
+      let data = bincode::serialize(&s).unwrap();
+      assert_eq!(s, bincode::deserialize(&data).unwrap());
+
+      let data = std::fs::read("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let p = Prefetch::new(&data[.. 0x12600]).unwrap();
+      let loaded: Prefetch =
+        bincode::deserialize(&bincode::serialize(&p).unwrap()).unwrap();
+      match loaded.volumes() {
+        Err(Error::SectionUnavailable(Section::Volumes)) => (),
+        _ => panic!("the unavailable section is not reported")
+      }
+    }
+
+    #[test]
+    fn writer() {
+      let sections = |p: &Prefetch| format!("{:?} {:?} {:?} {:?}",
+        p.last_execution_times(), p.metrics().unwrap().collect::<Vec<_>>(),
+        p.trace().unwrap().collect::<Vec<_>>(),
+        p.volumes().unwrap().collect::<Vec<_>>());
+
+      for path in &["assets/WUAUCLT.EXE-399A8E72.pf",
+          "assets/NOTEPAD.EXE-D8414F97.pf",
+          "assets/CMD.EXE-0BD30981.pf"] {
+        let p = Prefetch::from_path(path).unwrap();
+        let data = writer::Writer::from_prefetch(&p).unwrap().to_bytes();
+        let written = Prefetch::new(&data[..]).unwrap();
+        assert_eq!(p.name_utf16(), written.name_utf16());
+        assert_eq!(p.hash(), written.hash());
+        assert_eq!(p.execution_counter(), written.execution_counter());
+        assert_eq!(data.len(), written.size());
+        assert_eq!(sections(&p), sections(&written));
+        assert_eq!(data, writer::Writer::from_prefetch(&written).unwrap()
+          .to_bytes());
+      }
+
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      for version in &[FormatVersion::WindowsXp2003, FormatVersion::WindowsVista7,
+          FormatVersion::Windows8, FormatVersion::Windows10,
+          FormatVersion::Windows11] {
+        let data = writer::Writer::new(*version, p.name_utf16().clone(),
+            p.hash())
+          .last_execution_times(p.last_execution_times().to_vec())
+          .execution_counter(p.execution_counter())
+          .metrics(p.metrics().unwrap().cloned().collect())
+          .trace(p.trace().unwrap().cloned().collect())
+          .volumes(p.volumes().unwrap().cloned().collect())
+          .to_bytes();
+        let written = Prefetch::new(&data[..]).unwrap();
+        assert_eq!(version.value(), written.version().value());
+        assert_eq!(p.last_execution_time(), written.last_execution_time());
+        assert_eq!(6, written.execution_counter());
+        assert_eq!(p.metrics().unwrap().map(|m| m.filename()).collect::<Vec<_>>(),
+          written.metrics().unwrap().map(|m| m.filename()).collect::<Vec<_>>());
+        assert_eq!(9252, written.trace().unwrap().count());
+        let volume = written.volumes().unwrap().next().unwrap();
+        assert_eq!(6, volume.directories().unwrap().count());
+        assert_eq!(p.volumes().unwrap().next().unwrap().creation_time(),
+          volume.creation_time());
+        match *version {
+          FormatVersion::WindowsXp2003 => assert!(volume.file_references().is_err()),
+          _ => assert_eq!(5, volume.file_references().unwrap().count())
+        }
+      }
+    }
+
+    #[test]
+    fn compressor() {
+      let mut x = 1u32;
+      let mut noise = Vec::new();
+      for _ in 0 .. 200000 {
+        x = x.wrapping_mul(1103515245).wrapping_add(12345);
+        noise.push((x >> 16) as u8);
+      }
+      let mut inputs = vec![Vec::new(), vec![0x41], vec![0; 65536],
+        vec![0x5a; 300000], noise[.. 65536].to_vec(), noise.clone()];
+      // Runs of every match length escape, some crossing the blocks.
+      let mut runs = Vec::new();
+      for (i, length) in [5, 17, 40, 269, 270, 271, 1000, 70000].iter().enumerate() {
+        runs.extend(&noise[i * 10 .. i * 10 + 20]);
+        runs.extend(std::iter::repeat_n(i as u8, *length));
+      }
+      inputs.push(runs);
+      for path in &["assets/WUAUCLT.EXE-399A8E72.pf",
+          "assets/NOTEPAD.EXE-D8414F97.pf", "assets/CMD.EXE-0BD30981.pf"] {
+        inputs.push(std::fs::read(path).unwrap());
+      }
+
+      for input in &inputs {
+        for checksum in &[false, true] {
+          let compressed = compression::compress_mam(input, *checksum);
+          assert_eq!(*checksum, compressed[3] & 0x80 != 0);
+          assert_eq!(*input, compression::decompress_mam(&compressed).unwrap());
+        }
+      }
+
+      let data = std::fs::read("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let compressed = compression::compress_mam(&data, true);
+      assert!(compressed.len() < data.len() / 2);
+      let p = Prefetch::new(&compressed[..]).unwrap();
+      assert_eq!(9252, p.trace().unwrap().count());
+    }
+
+    #[test]
+    fn long_match() {
+      // A stream of one "A" and a match of 70003 bytes at offset 1: a literal
+      // and a match symbol of 1 bit each, the length escaped with a byte of
+      // 255, a 16 bits zero and the length on 32 bits.
+      let length = 70000u32;
+      let mut stream = b"MAM\x04".to_vec();
+      stream.extend(&(length + 4).to_le_bytes());
+      let mut table = [0u8; 256];
+      table[0x41 / 2] = 0x10;
+      table[(256 + 15) / 2] = 0x10;
+      stream.extend(&table[..]);
+      stream.extend(&[0x00, 0x40, 0x00, 0x00, 0xff, 0x00, 0x00]);
+      stream.extend(&length.to_le_bytes());
+      assert_eq!(vec![0x41; 70004], compression::decompress_mam(&stream).unwrap());
+
+      // The match may not run past the uncompressed size.
+      stream[4 .. 8].copy_from_slice(&(length + 3).to_le_bytes());
+      assert!(compression::decompress_mam(&stream).is_err());
+    }
+
+    #[test]
+    fn hash() {
+      use hash::HashAlgorithm;
+
+      for (path, device, algorithm) in &[
+          ("assets/WUAUCLT.EXE-399A8E72.pf",
+            r"\DEVICE\HARDDISKVOLUME1\WINDOWS\SYSTEM32\WUAUCLT.EXE",
+            HashAlgorithm::WindowsXp),
+          ("assets/NOTEPAD.EXE-D8414F97.pf",
+            r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\NOTEPAD.EXE",
+            HashAlgorithm::WindowsVista),
+          ("assets/NOTEPAD.EXE-D8414F97-MAM.pf",
+            r"\Device\HarddiskVolume2\Windows\System32\notepad.exe",
+            HashAlgorithm::WindowsVista),
+          ("assets/CMD.EXE-0BD30981.pf",
+            r"\DEVICE\HARDDISKVOLUME3\WINDOWS\SYSTEM32\CMD.EXE",
+            HashAlgorithm::WindowsVista)] {
+        let p = Prefetch::from_path(path).unwrap();
+        assert_eq!(Some(*algorithm), p.verify_hash(device));
+        assert_eq!(None, p.verify_hash(r"\DEVICE\HARDDISKVOLUME1\TEMP\CMD.EXE"));
+      }
+
+      assert_eq!(0x399a8e72,
+        hash::windows_xp(r"\DEVICE\HARDDISKVOLUME1\WINDOWS\SYSTEM32\WUAUCLT.EXE"));
+      assert_eq!(0x2f2d61e1,
+        hash::windows_xp(r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\NOTEPAD.EXE"));
+      assert_eq!(0x0bd30981,
+        hash::windows_2008(r"\DEVICE\HARDDISKVOLUME3\WINDOWS\SYSTEM32\CMD.EXE"));
+      assert_eq!(314159, hash::windows_vista(""));
+      assert_eq!(0, hash::windows_xp(""));
+      // The 2008 algorithm works on eight bytes at a time, check every tail.
+      let path = r"\DEVICE\HARDDISKVOLUME1\PROGRAM FILES\ÉDITEUR\ÉDITEUR.EXE";
+      for (i, _) in path.char_indices() {
+        assert_eq!(hash::windows_vista(&path[.. i]),
+          hash::windows_2008(&path[.. i]));
+      }
+      let units: Vec<u16> = path.to_uppercase().encode_utf16().collect();
+      assert_eq!(hash::windows_vista(path),
+        HashAlgorithm::WindowsVista.hash_units(&units));
+    }
+
+    #[test]
+    fn executable_path() {
+      use executable::Confidence;
+      use hash::HashAlgorithm;
+      use metric::MetricEntry;
+
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let e = p.executable_path().unwrap();
+      assert_eq!(r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\NOTEPAD.EXE", e.path());
+      assert_eq!(Confidence::HashMatch(HashAlgorithm::WindowsVista),
+        e.confidence());
+      assert_eq!(1, e.candidates().len());
+
+      // The volume is named by its serial, the hash cannot match.
+      let p = Prefetch::from_path("assets/CMD.EXE-0BD30981.pf").unwrap();
+      let e = p.executable_path().unwrap();
+      assert_eq!(r"\VOLUME{01d7b2a4c1e3f500-7e3f9a21}\WINDOWS\SYSTEM32\CMD.EXE",
+        e.path());
+      assert_eq!(Confidence::UniqueName, e.confidence());
+
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let metrics: Vec<MetricEntry> = p.metrics().unwrap().cloned().collect();
+      let write = |name: &str, hash: u32, filenames: &[&str]| {
+        let metrics = filenames.iter().enumerate()
+          .map(|(i, f)| MetricEntry::new(i, (*f).into(), 0, 0))
+          .collect();
+        let data = writer::Writer::new(FormatVersion::Windows10, name.into(),
+            hash)
+          .metrics(metrics)
+          .volumes(p.volumes().unwrap().cloned().collect())
+          .to_bytes();
+        Prefetch::new(&data[..]).unwrap().executable_path()
+      };
+
+      // Missing from the metrics, found in the directories.
+      let filenames: Vec<&str> = metrics.iter().map(|m| m.filename())
+        .filter(|f| !f.ends_with("NOTEPAD.EXE")).collect();
+      let e = write("NOTEPAD.EXE", p.hash(), &filenames).unwrap();
+      assert_eq!(r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\NOTEPAD.EXE", e.path());
+      assert!(e.candidates().is_empty());
+
+      let e = write("notepad.exe", 0x1234, &[
+          r"\DEVICE\HARDDISKVOLUME2\WINDOWS\NOTEPAD.EXE",
+          r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\NOTEPAD.EXE.MUI",
+          r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\NOTEPAD.EXE"]).unwrap();
+      assert_eq!(Confidence::Ambiguous, e.confidence());
+      assert_eq!(r"\DEVICE\HARDDISKVOLUME2\WINDOWS\NOTEPAD.EXE", e.path());
+      assert_eq!(2, e.candidates().len());
+
+      // Names are truncated to 29 characters.
+      let path = r"\DEVICE\HARDDISKVOLUME2\TOOLS\A_VERY_LONG_EXECUTABLE_NAME.EXE";
+      let e = write("A_VERY_LONG_EXECUTABLE_NAME.E", hash::windows_vista(path),
+        &[path]).unwrap();
+      assert_eq!(path, e.path());
+      assert_eq!(Confidence::HashMatch(HashAlgorithm::WindowsVista),
+        e.confidence());
+
+      assert!(write("CALC.EXE", p.hash(), &filenames).is_none());
+    }
+
+    #[test]
+    fn volume_id() {
+      use volume::{VolumeEntry, VolumeId};
+
+      let p = Prefetch::from_path("assets/CMD.EXE-0BD30981.pf").unwrap();
+      let volume = p.volumes().unwrap().next().unwrap();
+      assert_eq!(Some(volume.volume_id()), volume.parsed_volume_id());
+      assert_eq!(Some(true), volume.volume_id_matches());
+      let data = std::fs::read("assets/CMD.EXE-0BD30981.pf").unwrap();
+      let r = PrefetchRef::new(&data).unwrap();
+      let volume_ref = r.volumes().unwrap().next().unwrap().unwrap();
+      assert_eq!(Some(volume.volume_id()), volume_ref.parsed_volume_id());
+      assert_eq!(Some(true), volume_ref.volume_id_matches());
+
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let volume = p.volumes().unwrap().next().unwrap();
+      assert_eq!(None, volume.parsed_volume_id());
+      assert_eq!(None, volume.volume_id_matches());
+      assert_eq!(r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1d}", volume.volume_id().to_string());
+
+      let renamed = VolumeEntry::new(0,
+        r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1e}".into(), volume.creation_time(),
+        volume.serial_number());
+      assert_eq!(Some(false), renamed.volume_id_matches());
+
+      for path in &[r"\VOLUME{01d3c5f9a6b7c8d0}", r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1}",
+          r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1d}X", r"\VOLUME{+1d3c5f9a6b7c8d0-4a3b2c1d}",
+          r"\DEVICE\HARDDISKVOLUME2", r"\VOLUME{", "", "é"] {
+        assert_eq!(None, VolumeId::parse(path));
+      }
+      let (id, rest) = VolumeId::parse(r"\volume{01D3C5F9A6B7C8D0-4A3B2C1D}").unwrap();
+      assert_eq!(volume.volume_id(), id);
+      assert_eq!("", rest);
+
+      let expected = Some(r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1d}\USERS\ОТЧЁТ.TXT".to_string());
+      assert_eq!(expected,
+        p.normalize_path(r"\Device\HarddiskVolume2\Users\ОТЧЁТ.txt"));
+      assert_eq!(expected,
+        p.normalize_path(r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1d}\USERS\ОТЧЁТ.TXT"));
+      assert_eq!(expected,
+        p.normalize_path(r"\Device\HarddiskVolume2\Users\отчёт.txt\"));
+      assert_eq!(expected,
+        p.normalize_path(r"\volume{01d3c5f9a6b7c8d0-4a3b2c1d}\users\отчёт.txt"));
+      assert_eq!(Some(r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1d}".to_string()),
+        p.normalize_path(r"\DEVICE\HARDDISKVOLUME2\"));
+      assert_eq!(None, p.normalize_path(r"\DEVICE\HARDDISKVOLUME20\USERS"));
+      assert_eq!(None, p.normalize_path(r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1e}\USERS"));
+    }
+
+    #[test]
+    fn directory_tree() {
+      use directory::DirectoryTree;
+      use volume::VolumeEntry;
+
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let trees = DirectoryTree::from_prefetch(&p).unwrap();
+      assert_eq!(1, trees.len());
+      let tree = &trees[0];
+      assert_eq!(7, tree.len());
+      assert_eq!(vec![r"\DEVICE\HARDDISKVOLUME2", "USERS", "ANALYST", "DOCUMENTS",
+          "WINDOWS", "SYSTEM32", "EN-US"],
+        tree.iter().map(|n| tree.node(n).name()).collect::<Vec<_>>());
+      assert!(tree.iter().all(|n| tree.node(n).is_listed()));
+
+      let system32 = tree.find(r"\device\harddiskvolume2\windows\system32\")
+        .unwrap();
+      assert_eq!(Some(system32),
+        tree.find(r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1d}\WINDOWS\SYSTEM32"));
+      let node = tree.node(system32);
+      assert_eq!(vec![(0, "NTDLL.DLL"), (1, "KERNEL32.DLL"), (2, "KERNELBASE.DLL"),
+          (3, "NOTEPAD.EXE")],
+        node.files().iter().map(|f| (f.metric_id(), f.name())).collect::<Vec<_>>());
+      assert_eq!("EN-US", tree.node(node.children()[0]).name());
+      let windows = node.parent().unwrap();
+      assert_eq!(r"\DEVICE\HARDDISKVOLUME2\WINDOWS", tree.path(windows));
+      assert_eq!(Some(tree.root()), tree.node(windows).parent());
+      assert_eq!(None, tree.node(tree.root()).parent());
+      assert_eq!(Some(tree.root()), tree.find(r"\DEVICE\HARDDISKVOLUME2"));
+      assert_eq!(None, tree.find(r"\DEVICE\HARDDISKVOLUME2\TEMP"));
+      assert_eq!(None, tree.find(r"\DEVICE\HARDDISKVOLUME20\WINDOWS"));
+      let documents = tree.find(r"\DEVICE\HARDDISKVOLUME2\USERS\ANALYST\DOCUMENTS")
+        .unwrap();
+      assert_eq!("ОТЧЁТ.TXT", tree.node(documents).files()[0].name());
+
+      // Directories of the metrics missing from the list are added, unlisted.
+      let p = Prefetch::from_path("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+      let tree = &DirectoryTree::from_prefetch(&p).unwrap()[0];
+      assert_eq!(r"\DEVICE\HARDDISKVOLUME1", tree.node(tree.root()).name());
+      assert_eq!(p.metrics().unwrap().count(),
+        tree.iter().map(|n| tree.node(n).files().len()).sum::<usize>());
+      let drivers = tree.find(r"\DEVICE\HARDDISKVOLUME1\WINDOWS\SYSTEM32\DRIVERS")
+        .unwrap();
+      assert!(!tree.node(drivers).is_listed());
+      assert!(tree.node(tree.node(drivers).parent().unwrap()).is_listed());
+      assert_eq!("KMIXER.SYS", tree.node(drivers).files()[0].name());
+
+      let volume = VolumeEntry::new(0, r"\DEVICE\HARDDISKVOLUME4".into(),
+          FileTime::new(0), 0)
+        .with_directories(vec![r"\DEVICE\HARDDISKVOLUME4\ПАПКА\".into(),
+          r"\DEVICE\HARDDISKVOLUME5\TEMP".into()]);
+      let tree = DirectoryTree::new(&volume);
+      assert_eq!(2, tree.len());
+      assert!(!tree.is_empty());
+      assert_eq!(tree.find(r"\DEVICE\HARDDISKVOLUME4\ПАПКА"),
+        tree.find(r"\Device\HarddiskVolume4\папка"));
+
+      // Paths longer than NTFS allows are dropped, they bound the depth.
+      let mut deep = DirectoryTree::new(&volume);
+      let path = |depth| format!(r"\DEVICE\HARDDISKVOLUME4{}\X.DLL",
+        r"\A".repeat(depth));
+      let metric = metric::MetricEntry::new(0, path(16370).as_str().into(), 0, 0);
+      assert_eq!(32769, metric.filename_utf16().units().len());
+      assert_eq!(None, deep.insert_file(&metric));
+      let metric = metric::MetricEntry::new(0, path(16369).as_str().into(), 0, 0);
+      assert!(deep.insert_file(&metric).is_some());
+      assert_eq!(tree.len() + 16369, deep.len());
+      #[cfg(feature = "serde")]
+      {
+        assert!(serde_json::to_string(&deep).is_err());
+        let mut deep = DirectoryTree::new(&volume);
+        deep.insert_file(&metric::MetricEntry::new(0, path(1024).as_str().into(),
+          0, 0));
+        assert!(serde_json::to_string(&deep).unwrap().ends_with(&"]}".repeat(1025)));
+      }
+
+      #[cfg(feature = "serde")]
+      {
+        let p = Prefetch::from_path("assets/CMD.EXE-0BD30981.pf").unwrap();
+        let trees = DirectoryTree::from_prefetch(&p).unwrap();
+        let json = serde_json::to_value(&trees[0]).unwrap();
+        assert_eq!(r"\VOLUME{01d7b2a4c1e3f500-7e3f9a21}", json["name"]["Str"]);
+        let system32 = &json["children"][1]["children"][0];
+        assert_eq!("SYSTEM32", system32["name"]["Str"]);
+        assert_eq!(serde_json::json!({"metric_id": 2, "name": {"Str": "CMD.EXE"}}),
+          system32["files"][2]);
+      }
+    }
+
+    #[test]
+    fn hosting() {
+      use metric::MetricEntry;
+
+      let path = r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\SVCHOST.EXE";
+      let p = Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+      let svchost = |version, command_line: &str| {
+        let algorithm = hash::HashAlgorithm::candidates(version)[0];
+        let data = writer::Writer::new(version, "SVCHOST.EXE".into(),
+            hosting::hash(algorithm, path, command_line))
+          .metrics(vec![
+            MetricEntry::new(0, r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\NTDLL.DLL".into(), 0, 0),
+            MetricEntry::new(1, path.into(), 0, 0)])
+          .volumes(p.volumes().unwrap().cloned().collect())
+          .to_bytes();
+        Prefetch::new(&data[..]).unwrap()
+      };
+
+      let candidates = hosting::svchost_command_lines(r"C:\Windows\");
+      assert_eq!(2 * hosting::SVCHOST_GROUPS.len(), candidates.len());
+      assert!(candidates.contains(
+        &r"C:\Windows\SYSTEM32\SVCHOST.EXE -k netsvcs -p".to_string()));
+
+      let s = svchost(FormatVersion::Windows10,
+        r"c:\windows\system32\svchost.exe -k NetSvcs -p");
+      assert!(hosting::is_hosting_executable(s.name()));
+      assert_eq!(None, s.verify_hash(path));
+      let m = hosting::match_command_line(&s, &candidates).unwrap();
+      assert_eq!(path, m.path());
+      assert_eq!(r"C:\Windows\SYSTEM32\SVCHOST.EXE -k netsvcs -p",
+        m.command_line());
+      assert_eq!(hash::HashAlgorithm::WindowsVista, m.algorithm());
+
+      let s = svchost(FormatVersion::WindowsXp2003,
+        r"C:\WINDOWS\System32\svchost.exe -k DcomLaunch");
+      let m = hosting::match_command_line(&s, &candidates).unwrap();
+      assert_eq!(r"C:\Windows\SYSTEM32\SVCHOST.EXE -k DcomLaunch",
+        m.command_line());
+      assert_eq!(hash::HashAlgorithm::WindowsXp, m.algorithm());
+
+      let s = svchost(FormatVersion::Windows10,
+        r"C:\WINDOWS\SYSTEM32\SVCHOST.EXE -k MyGroup");
+      assert!(hosting::match_command_line(&s, &candidates).is_none());
+      let custom = [r"C:\WINDOWS\SYSTEM32\SVCHOST.EXE -k mygroup"];
+      assert_eq!(custom[0],
+        hosting::match_command_line(&s, &custom).unwrap().command_line());
+
+      assert!(!hosting::is_hosting_executable(p.name()));
+      assert!(hosting::is_hosting_executable("rundll32.exe"));
+
+      // Hashes of the path followed by the command line, computed with the
+      // same construction by a separate implementation; they pin the
+      // construction, they are not checked against a file written by Windows.
+      let netsvcs = r"C:\WINDOWS\SYSTEM32\SVCHOST.EXE -k netsvcs";
+      assert_eq!(0x6ea48338,
+        hosting::hash(hash::HashAlgorithm::WindowsVista, path, netsvcs));
+      assert_eq!(0x0e271a1d,
+        hosting::hash(hash::HashAlgorithm::WindowsXp, path, netsvcs));
+      assert_eq!(0x3d27331f, hosting::hash(hash::HashAlgorithm::Windows2008,
+        path, r"C:\WINDOWS\SYSTEM32\SVCHOST.EXE -k LocalService -p"));
+      let data = writer::Writer::new(FormatVersion::Windows10,
+          "SVCHOST.EXE".into(), 0x6ea48338)
+        .metrics(vec![MetricEntry::new(0, path.into(), 0, 0)])
+        .volumes(p.volumes().unwrap().cloned().collect())
+        .to_bytes();
+      let s = Prefetch::new(&data[..]).unwrap();
+      let candidates = hosting::svchost_command_lines(r"C:\WINDOWS");
+      assert_eq!(netsvcs,
+        hosting::match_command_line(&s, &candidates).unwrap().command_line());
+    }
+
+    #[test]
+    fn analysis() {
+      use analysis::{Analyzer, Rule, Severity};
+      use metric::MetricEntry;
+      use volume::VolumeEntry;
+
+      for path in &["assets/WUAUCLT.EXE-399A8E72.pf",
+          "assets/NOTEPAD.EXE-D8414F97.pf"] {
+        assert!(Analyzer::new().analyze(&Prefetch::from_path(path).unwrap())
+          .is_empty());
+      }
+
+      let ntdll = r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\NTDLL.DLL";
+      let write = |executable: &str, filenames: &[&str]| {
+        let name = executable.rsplit('\\').next().unwrap();
+        let metrics = filenames.iter().chain(Some(&executable))
+          .enumerate()
+          .map(|(i, f)| MetricEntry::new(i, (*f).into(), 0, 0))
+          .collect();
+        let volumes = vec![
+          VolumeEntry::new(0, r"\DEVICE\HARDDISKVOLUME2".into(),
+            FileTime::new(0x01d3c5f9a6b7c8d0), 0x4a3b2c1d),
+          VolumeEntry::new(1, r"\DEVICE\HARDDISKVOLUME5".into(),
+            FileTime::new(0x01d9a0b1c2d3e4f5), 0x0badf00d)];
+        let data = writer::Writer::new(FormatVersion::Windows10, name.into(),
+            hash::windows_vista(executable))
+          .metrics(metrics)
+          .volumes(volumes)
+          .to_bytes();
+        Prefetch::new(&data[..]).unwrap()
+      };
+      let summary = |p: &Prefetch, analyzer: &Analyzer| analyzer.analyze(p)
+        .iter()
+        .map(|f| (f.rule(), f.severity(), f.path().to_string(),
+          f.explanation().to_string()))
+        .collect::<Vec<_>>();
+
+      let exe = r"\DEVICE\HARDDISKVOLUME2\USERS\ANALYST\DOWNLOADS\SCVHOST.EXE";
+      let hook = r"\DEVICE\HARDDISKVOLUME2\ProgramData\Vendor\hook.dll";
+      let p = write(exe, &[ntdll, hook]);
+      assert_eq!(vec![
+          (Rule::SuspiciousDirectory, Severity::High, exe.to_string(),
+            "SCVHOST.EXE ran from a Downloads folder".to_string()),
+          (Rule::Masquerading, Severity::High, exe.to_string(),
+            "SCVHOST.EXE imitates SVCHOST.EXE".to_string()),
+          (Rule::UserWritableLibrary, Severity::Medium, hook.to_string(),
+            "HOOK.DLL was loaded from a user-writable folder".to_string())],
+        summary(&p, &Analyzer::new()));
+      assert_eq!(vec![Rule::UserWritableLibrary, Rule::SuspiciousDirectory],
+        Analyzer::new().rules(vec![Rule::UserWritableLibrary, Rule::SuspiciousDirectory])
+          .analyze(&p).iter().map(|f| f.rule()).collect::<Vec<_>>());
+      assert_eq!(2, Analyzer::new().without(Rule::Masquerading).analyze(&p).len());
+      assert!(Analyzer::new().rules(vec![]).analyze(&p).is_empty());
+
+      let exe = r"\DEVICE\HARDDISKVOLUME5\TOOLS\SVCHOST.EXE";
+      let p = write(exe, &[ntdll]);
+      assert_eq!(vec![
+          (Rule::NonSystemVolume, Severity::Medium, exe.to_string(),
+            r"SVCHOST.EXE ran from \DEVICE\HARDDISKVOLUME5, Windows is on \DEVICE\HARDDISKVOLUME2".to_string()),
+          (Rule::Masquerading, Severity::High, exe.to_string(),
+            r"SVCHOST.EXE ran outside of \WINDOWS\SYSTEM32".to_string())],
+        summary(&p, &Analyzer::new()));
+
+      for exe in &[r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSWOW64\SVCHOST.EXE",
+          r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\TASKHOST.EXE",
+          r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\TASKHOSTW.EXE"] {
+        let p = write(exe, &[ntdll]);
+        assert!(Analyzer::new().analyze(&p).is_empty(), "{}", exe);
+      }
+      let exe = r"\DEVICE\HARDDISKVOLUME2\TOOLS\TASKHOSTW.EXE";
+      assert_eq!(vec![(Rule::Masquerading, Severity::High, exe.to_string(),
+          r"TASKHOSTW.EXE ran outside of \WINDOWS\SYSTEM32".to_string())],
+        summary(&write(exe, &[ntdll]), &Analyzer::new()));
+      let p = write(r"\DEVICE\HARDDISKVOLUME2\WINDOWS\SYSTEM32\LSA55.EXE",
+        &[ntdll]);
+      let findings = Analyzer::new().analyze(&p);
+      assert_eq!("LSA55.EXE imitates LSASS.EXE", findings[0].explanation());
+      assert_eq!("[high] masquerading: LSA55.EXE imitates LSASS.EXE",
+        findings[0].to_string());
+
+      let p = write(r"\DEVICE\HARDDISKVOLUME2\WINDOWS\TEMP\X.EXE",
+        &[ntdll, r"\DEVICE\HARDDISKVOLUME2\WINDOWS\TEMP\X.DLL"]);
+      assert_eq!(vec![Severity::High, Severity::High],
+        Analyzer::new().analyze(&p).iter().map(|f| f.severity()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(feature = "registry")]
+    fn registry() {
+      use registry::DriveMap;
+
+      let mut drives = DriveMap::from_path("assets/SYSTEM").unwrap();
+      assert_eq!(vec![
+          (r"\??\VOLUME{3F1C2E4A-0000-0000-0000-1D1C50000000}", 'D'),
+          (r"\??\VOLUME{3F1C2E4A-0000-0000-0000-6500000000}", 'C'),
+          (r"\??\VOLUME{7D2A9C10-1111-11EF-A1B2-806E6F6E6963}", 'E')],
+        drives.iter().collect::<Vec<_>>());
+      assert_eq!(None, drives.drive_letter(r"\DEVICE\HARDDISKVOLUME2"));
+      drives.insert(r"\DEVICE\HARDDISKVOLUME2", 'c');
+      drives.insert(r"\Device\HarddiskVolume3\", 'D');
+      assert_eq!(Some('D'), drives.drive_letter(r"\Device\HarddiskVolume3\"));
+      assert_eq!(None, drives.drive_letter(r"\DEVICE\HARDDISKVOLUME1"));
+
+      assert_eq!(Some(r"C:\".to_string()),
+        drives.rewrite(r"\DEVICE\HARDDISKVOLUME2"));
+      assert_eq!(Some(r"D:\Users\ОТЧЁТ.txt".to_string()),
+        drives.rewrite(r"\Device\HarddiskVolume3\Users\ОТЧЁТ.txt"));
+      assert_eq!(None, drives.rewrite(r"\DEVICE\HARDDISKVOLUME20\WINDOWS"));
+
+      let p = Prefetch::from_path("assets/CMD.EXE-0BD30981.pf").unwrap();
+      let path = p.executable_path().unwrap();
+      assert_eq!(None, drives.rewrite(path.path()));
+      drives.insert(r"\volume{01d7b2a4c1e3f500-7e3f9a21}\", 'f');
+      assert_eq!(r"F:\WINDOWS\SYSTEM32\CMD.EXE", drives.rewrite(path.path()).unwrap());
+
+      let data = std::fs::read("assets/SYSTEM").unwrap();
+      match DriveMap::from_hive(data[.. 0x1200].to_vec()) {
+        Err(Error::InvalidHive(_)) => (),
+        r => panic!("{:?}", r)
+      }
+      let pos = data.windows(14).position(|w| w == b"MountedDevices").unwrap();
+      let mut renamed = data.clone();
+      renamed[pos + 13] = b'z';
+      match DriveMap::from_hive(renamed) {
+        Err(Error::RegistryKeyNotFound(ref k)) if k == "MountedDevices" => (),
+        r => panic!("{:?}", r)
+      }
+
+      // Counts and sizes larger than the hive are rejected before reserving
+      // memory for them.
+      let nk = pos - 0x4c;
+      for count in &[8u32, 0x1000, 0xffffffff] {
+        let mut malformed = data.clone();
+        malformed[nk + 0x24 .. nk + 0x28].copy_from_slice(&count.to_le_bytes());
+        match DriveMap::from_hive(malformed) {
+          Err(Error::InvalidHive(_)) => (),
+          r => panic!("{:?}", r)
+        }
+      }
+      let vk = data.windows(14).position(|w| w == br"\DosDevices\C:").unwrap()
+        - 0x14;
+      let cell = 0x1000 + u32::from_le_bytes([data[vk + 8], data[vk + 9],
+        data[vk + 10], data[vk + 11]]) as usize + 4;
+      let mut malformed = data.clone();
+      malformed[vk + 4 .. vk + 8].copy_from_slice(&0x7fff_0000u32.to_le_bytes());
+      malformed[cell .. cell + 2].copy_from_slice(b"db");
+      match DriveMap::from_hive(malformed) {
+        Err(Error::InvalidHive(_)) => (),
+        r => panic!("{:?}", r)
+      }
+    }
+
     #[test]
     fn readme() {
       let file = std::fs::File::open("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
@@ -173,15 +1237,15 @@ mod tests {
       println!("Executable {} launched {} times. The last time was: {}",
         prefetch.name(),
         prefetch.execution_counter(),
-        prefetch.last_execution_time() // TODO: format the MSTIME here
+        prefetch.last_execution_time()
       );
 
       // Iterates over all loaded DLL etc for the prefetch file
       println!(" ===== File metrics ===== ");
       for metric in prefetch.metrics().unwrap() {
         println!("#{}: {}", metric.id(), metric.filename());
-        println!("    start time: {}", metric.start_time().unwrap());
-        println!("    duration: {}", metric.duration().unwrap());
+        println!("    trace entries: {} from #{}", metric.trace_entry_count(),
+          metric.trace_chain_index());
         println!(" ------------------------------- ");
       }
 
@@ -16,38 +16,95 @@
 // Authors: zadig <thomas chr(0x40) bailleux.me>
 //          jasa <jan.starke (0x40) t-systems.com>
 use std;
-use super::util::FromSlice;
+use super::error::Section;
+use super::util::{FromSlice, read};
 
 /// A file metric entry.
 ///
 /// This is supposed to give some information about
 /// a file which is load by the exe.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MetricEntry {
   id: usize,
-  start_time: Option<u32>,
-  duration: Option<u32>,
   average_duration: Option<u32>,
-  filename: std::string::String,
+  filename: super::utf16::Utf16String,
   mft_entry_index: Option<u64>,
-  sequence_number: Option<u16>
+  sequence_number: Option<u16>,
+  trace_chain_index: usize,
+  trace_entry_count: usize,
+  flags: u32
 }
 
 impl MetricEntry {
 
+  /// Constructs an entry for `filename`, whose trace chain entries are the
+  /// `trace_entry_count` ones from `trace_chain_index`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use libprefetch::metric::MetricEntry;
+  ///
+  /// let metric = MetricEntry::new(0,
+  ///     "\\DEVICE\\HARDDISKVOLUME1\\WINDOWS\\SYSTEM32\\NTDLL.DLL".into(), 0, 12)
+  ///   .with_file_reference(0x1f3a, 1)
+  ///   .with_flags(0x0202);
+  /// assert_eq!(Some(0x1f3a), metric.mft_entry_index());
+  /// ```
+  pub fn new(id: usize, filename: super::utf16::Utf16String,
+      trace_chain_index: usize, trace_entry_count: usize) -> MetricEntry {
+    MetricEntry {
+      id,
+      average_duration: None,
+      filename,
+      mft_entry_index: None,
+      sequence_number: None,
+      trace_chain_index,
+      trace_entry_count,
+      flags: 0
+    }
+  }
+
+  /// Sets the average duration, stored since Windows Vista.
+  pub fn with_average_duration(mut self, average_duration: u32) -> MetricEntry {
+    self.average_duration = Some(average_duration);
+    self
+  }
+
+  /// Sets the NTFS file reference, stored since Windows Vista.
+  pub fn with_file_reference(mut self, mft_entry_index: u64,
+      sequence_number: u16) -> MetricEntry {
+    self.mft_entry_index = Some(mft_entry_index);
+    self.sequence_number = Some(sequence_number);
+    self
+  }
+
+  /// Sets the flags.
+  pub fn with_flags(mut self, flags: u32) -> MetricEntry {
+    self.flags = flags;
+    self
+  }
+
   /// Returns the ID of the entry.
   pub fn id(&self) -> usize {
     self.id
   }
 
-  /// Returns the start time (when the file is loaded).
+  /// Returns the first field of the entry, formerly read as a start time.
+  ///
+  /// The field is the index of the first trace chain entry of the file.
+  #[deprecated(note = "aliases `trace_chain_index`, the entry stores no start time")]
   pub fn start_time(&self) -> Option<u32> {
-    self.start_time
+    Some(self.trace_chain_index as u32)
   }
 
-  /// Returns the duration (loading duration).
+  /// Returns the second field of the entry, formerly read as a duration.
+  ///
+  /// The field is the number of trace chain entries of the file.
+  #[deprecated(note = "aliases `trace_entry_count`, the entry stores no duration")]
   pub fn duration(&self) -> Option<u32> {
-    self.duration
+    Some(self.trace_entry_count as u32)
   }
 
   /// Returns the average duration.
@@ -57,6 +114,11 @@ impl MetricEntry {
 
   /// Returns the filename.
   pub fn filename(&self) -> &str {
+    self.filename.as_str()
+  }
+
+  /// Returns the filename, as stored in the file.
+  pub fn filename_utf16(&self) -> &super::utf16::Utf16String {
     &self.filename
   }
 
@@ -69,167 +131,261 @@ impl MetricEntry {
   pub fn sequence_number(&self) -> Option<u16> {
     self.sequence_number
   }
-}
 
+  /// Returns the index of the first trace chain entry of the file.
+  pub fn trace_chain_index(&self) -> usize {
+    self.trace_chain_index
+  }
 
-pub(crate) trait MetricParser {
+  /// Returns how many trace chain entries belong to the file.
+  pub fn trace_entry_count(&self) -> usize {
+    self.trace_entry_count
+  }
 
-  fn parse_metrics(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<super::metric::MetricEntry>>;
+  /// Returns the flags of the entry.
+  pub fn flags(&self) -> u32 {
+    self.flags
+  }
+
+  /// Returns the trace chain entries which belong to the file, that is the
+  /// blocks of the file which were loaded.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use libprefetch::Prefetch;
+  ///
+  /// let prefetch =
+  ///     Prefetch::from_path("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+  ///
+  /// for metric in prefetch.metrics().unwrap() {
+  ///   let blocks: usize = metric.trace_entries(&prefetch).unwrap().iter()
+  ///     .map(|t| t.load_count()).sum();
+  ///   println!("{}: {}", metric.filename(), blocks);
+  /// }
+  /// ```
+  pub fn trace_entries<'p>(&self, prefetch: &'p super::Prefetch)
+      -> super::Result<&'p [super::trace::TraceEntry]> {
+    match *prefetch.parser_result.trace() {
+      Ok(ref v) => self.trace_chain_index.checked_add(self.trace_entry_count)
+        .and_then(|end| v.get(self.trace_chain_index .. end))
+        .ok_or(super::error::Error::EntryOutOfBounds(Section::TraceChain,
+          self.trace_chain_index, self.trace_entry_count)),
+      Err(ref e) => Err(e.duplicate())
+    }
+  }
 }
 
-impl MetricParser for super::parser::WindowsXp2003 {
 
-  fn parse_metrics(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<super::metric::MetricEntry>> {
-    let offset = usize::from_slice(&content[0x54 .. 0x58]);
-    let n = usize::from_slice(&content[0x58 .. 0x5c]);
-    let mut entries = std::vec::Vec::<MetricEntry>::with_capacity(n);
-    let entry_size = 20usize;
-    let section = &content[offset .. offset + n * entry_size];
-    let name_section_offset = usize::from_slice(&content[0x64 .. 0x68]);
-    let name_section_length = usize::from_slice(&content[0x68 .. 0x6c]);
-    let name_section = &content[name_section_offset .. name_section_offset +
-      name_section_length];
-
-    // Each entry is 20 bytes
-    for i in 0 .. n {
-      let entry = &section[entry_size * i .. entry_size * (i + 1)];
-
-      let name_offset = usize::from_slice(&entry[0x8 .. 0xc]);
-      let name_length = usize::from_slice(&entry[0xc .. 0x10]);
-
-      entries.push(MetricEntry {
-        id: i,
-        start_time: Some(u32::from_slice(&entry[0x0 .. 0x4])),
-        duration: Some(u32::from_slice(&entry[0x4 .. 0x8])),
-        average_duration: None,
-        filename: super::util::fetch_unicode_string(&name_section, name_offset,
-        name_length),
-        mft_entry_index: None,
-        sequence_number: None
-      });
-    }
+/// A file metric entry, borrowed from the data of a `PrefetchRef`.
+///
+/// This is the borrowed counterpart of `MetricEntry`: the filename is only
+/// decoded when asked for.
+#[derive(Debug, Clone, Copy)]
+pub struct MetricEntryRef<'a> {
+  id: usize,
+  average_duration: Option<u32>,
+  filename: super::utf16::Utf16Str<'a>,
+  mft_entry_index: Option<u64>,
+  sequence_number: Option<u16>,
+  trace_chain_index: usize,
+  trace_entry_count: usize,
+  flags: u32
+}
+
+impl<'a> MetricEntryRef<'a> {
 
-    Ok(entries)
+  /// Returns the ID of the entry.
+  pub fn id(&self) -> usize {
+    self.id
   }
 
-}
+  /// Returns the first field of the entry, formerly read as a start time.
+  ///
+  /// The field is the index of the first trace chain entry of the file.
+  #[deprecated(note = "aliases `trace_chain_index`, the entry stores no start time")]
+  pub fn start_time(&self) -> Option<u32> {
+    Some(self.trace_chain_index as u32)
+  }
 
-impl MetricParser for super::parser::WindowsVista7 {
+  /// Returns the second field of the entry, formerly read as a duration.
+  ///
+  /// The field is the number of trace chain entries of the file.
+  #[deprecated(note = "aliases `trace_entry_count`, the entry stores no duration")]
+  pub fn duration(&self) -> Option<u32> {
+    Some(self.trace_entry_count as u32)
+  }
 
-  fn parse_metrics(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<super::metric::MetricEntry>> {
+  /// Returns the average duration.
+  pub fn average_duration(&self) -> Option<u32> {
+    self.average_duration
+  }
 
-    let offset = usize::from_slice(&content[0x54 .. 0x58]);
-    let n = usize::from_slice(&content[0x58 .. 0x5c]);
-    let mut entries = std::vec::Vec::<MetricEntry>::with_capacity(n);
-    let entry_size = 32;
-    let section = &content[offset .. offset + n * entry_size];
-    let name_section_offset = usize::from_slice(&content[0x64 .. 0x68]);
-    let name_section_length = usize::from_slice(&content[0x68 .. 0x6c]);
-    let name_section = &content[name_section_offset .. name_section_offset +
-      name_section_length];
-
-    for i in 0 .. n {
-      let entry = &section[entry_size * i .. entry_size * (i + 1)];
-
-      let name_offset = usize::from_slice(&entry[0xc .. 0x10]);
-      let name_length = usize::from_slice(&entry[0x10 .. 0x14]);
-
-      entries.push(MetricEntry {
-        id: i,
-        start_time: Some(u32::from_slice(&entry[0x0 .. 0x4])),
-        duration: Some(u32::from_slice(&entry[0x4 .. 0x8])),
-        average_duration: Some(u32::from_slice(&entry[0x8 .. 0xc])),
-        filename: super::util::fetch_unicode_string(&name_section, name_offset,
-        name_length),
-        mft_entry_index: Some(u64::from_slice(&entry[0x18 .. 0x1e])),
-        sequence_number: Some(u32::from_slice(&entry[0x1e .. 0x20]) as u16)
-      });
-    }
+  /// Returns the filename.
+  pub fn filename(&self) -> super::utf16::Utf16Str<'a> {
+    self.filename
+  }
+
+  /// Returns the NTFS MFT entry index, if available.
+  pub fn mft_entry_index(&self) -> Option<u64> {
+    self.mft_entry_index
+  }
 
-    Ok(entries)
+  /// Returns the NTFS sequence number, if available.
+  pub fn sequence_number(&self) -> Option<u16> {
+    self.sequence_number
   }
 
-}
+  /// Returns the index of the first trace chain entry of the file.
+  pub fn trace_chain_index(&self) -> usize {
+    self.trace_chain_index
+  }
 
-impl MetricParser for super::parser::Windows8 {
+  /// Returns how many trace chain entries belong to the file.
+  pub fn trace_entry_count(&self) -> usize {
+    self.trace_entry_count
+  }
 
-  fn parse_metrics(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<super::metric::MetricEntry>> {
+  /// Returns the flags of the entry.
+  pub fn flags(&self) -> u32 {
+    self.flags
+  }
 
-    let offset = usize::from_slice(&content[0x54 .. 0x58]);
-    let n = usize::from_slice(&content[0x58 .. 0x5c]);
-    let mut entries = std::vec::Vec::<MetricEntry>::with_capacity(n);
-    let entry_size = 32;
-    let section = &content[offset .. offset + n * entry_size];
-    let name_section_offset = usize::from_slice(&content[0x64 .. 0x68]);
-    let name_section_length = usize::from_slice(&content[0x68 .. 0x6c]);
-    let name_section = &content[name_section_offset .. name_section_offset +
-      name_section_length];
-
-    // Each entry is 20 bytes
-    for i in 0 .. n {
-      let entry = &section[entry_size * i .. entry_size * (i + 1)];
-
-      let name_offset = usize::from_slice(&entry[0xc .. 0x10]);
-      let name_length = usize::from_slice(&entry[0x10 .. 0x14]);
-
-
-      entries.push(MetricEntry {
-        id: i,
-        start_time: Some(u32::from_slice(&entry[0x0 .. 0x4])),
-        duration: Some(u32::from_slice(&entry[0x4 .. 0x8])),
-        average_duration: Some(u32::from_slice(&entry[0x8 .. 0xc])),
-        filename: super::util::fetch_unicode_string(&name_section, name_offset,
-        name_length),
-        mft_entry_index: Some(u64::from_slice(&entry[0x18 .. 0x1e])),
-        sequence_number: Some(u32::from_slice(&entry[0x1e .. 0x20]) as u16)
-      });
+  /// Copies the entry into an owned `MetricEntry`.
+  pub fn to_entry(&self) -> MetricEntry {
+    MetricEntry {
+      id: self.id,
+      average_duration: self.average_duration,
+      filename: self.filename.to_utf16_string(),
+      mft_entry_index: self.mft_entry_index,
+      sequence_number: self.sequence_number,
+      trace_chain_index: self.trace_chain_index,
+      trace_entry_count: self.trace_entry_count,
+      flags: self.flags
     }
+  }
+}
+
+/// Offsets of the fields of a metric entry.
+pub(crate) struct MetricLayout {
+  pub(crate) entry_size: usize,
+  name_offset: usize,
+  name_length: usize,
+  average_duration: Option<usize>,
+  file_reference: Option<usize>,
+  flags: usize
+}
 
-    Ok(entries)
+// Each entry is 20 bytes
+const WINDOWS_XP_2003: MetricLayout = MetricLayout {
+  entry_size: 20,
+  name_offset: 0x8,
+  name_length: 0xc,
+  average_duration: None,
+  file_reference: None,
+  flags: 0x10
+};
+
+const WINDOWS_VISTA: MetricLayout = MetricLayout {
+  entry_size: 32,
+  name_offset: 0xc,
+  name_length: 0x10,
+  average_duration: Some(0x8),
+  file_reference: Some(0x18),
+  flags: 0x14
+};
+
+impl MetricLayout {
+
+  pub(crate) fn parse_entry<'a>(&self, id: usize, entry: &'a [u8],
+      names: &'a [u8]) -> super::Result<MetricEntryRef<'a>> {
+    let name_offset = usize::from_slice(
+      &entry[self.name_offset .. self.name_offset + 4]);
+    let name_length = usize::from_slice(
+      &entry[self.name_length .. self.name_length + 4]);
+
+    Ok(MetricEntryRef {
+      id,
+      average_duration: self.average_duration
+        .map(|o| u32::from_slice(&entry[o .. o + 4])),
+      filename: super::util::fetch_unicode_string(names, Section::Filenames,
+        name_offset, name_length)?,
+      mft_entry_index: self.file_reference
+        .map(|o| u64::from_slice(&entry[o .. o + 6])),
+      sequence_number: self.file_reference
+        .map(|o| u16::from_slice(&entry[o + 6 .. o + 8])),
+      trace_chain_index: usize::from_slice(&entry[0x0 .. 0x4]),
+      trace_entry_count: usize::from_slice(&entry[0x4 .. 0x8]),
+      flags: u32::from_slice(&entry[self.flags .. self.flags + 4])
+    })
   }
 
+  /// Writes `entry` into `out`, its filename being at `name_offset` of the
+  /// filename strings.
+  pub(crate) fn write_entry(&self, entry: &MetricEntry, name_offset: usize,
+      out: &mut [u8]) {
+    use super::util::write;
+
+    write(out, 0x0, 4, entry.trace_chain_index as u64);
+    write(out, 0x4, 4, entry.trace_entry_count as u64);
+    write(out, self.name_offset, 4, name_offset as u64);
+    write(out, self.name_length, 4, entry.filename.units().len() as u64);
+    write(out, self.flags, 4, entry.flags as u64);
+    if let Some(o) = self.average_duration {
+      write(out, o, 4, entry.average_duration.unwrap_or(0) as u64);
+    }
+    if let Some(o) = self.file_reference {
+      write(out, o, 6, entry.mft_entry_index.unwrap_or(0));
+      write(out, o + 6, 2, entry.sequence_number.unwrap_or(0) as u64);
+    }
+  }
+
+  /// Returns the metrics array and the filename strings of `content`.
+  pub(crate) fn sections<'a>(&self, content: &'a [u8])
+      -> super::Result<(&'a [u8], &'a [u8])> {
+    let offset: usize = read(content, Section::FileInformation, 0x54, 4)?;
+    let n: usize = read(content, Section::FileInformation, 0x58, 4)?;
+    let name_section_offset: usize =
+      read(content, Section::FileInformation, 0x64, 4)?;
+    let name_section_length: usize =
+      read(content, Section::FileInformation, 0x68, 4)?;
+
+    Ok((
+      super::util::entries(content, Section::Metrics, offset, n,
+        self.entry_size)?,
+      super::util::slice(content, Section::Filenames, name_section_offset,
+        name_section_length)?
+    ))
+  }
 }
 
-impl MetricParser for super::parser::Windows10 {
+pub(crate) trait MetricParser {
+
+  fn metric_layout(&self) -> &'static MetricLayout {
+    &WINDOWS_VISTA
+  }
+
+  fn metric_entry_size(&self) -> usize {
+    self.metric_layout().entry_size
+  }
 
   fn parse_metrics(&self, content: &[u8])
     -> super::Result<std::vec::Vec<super::metric::MetricEntry>> {
+    super::iterator::MetricRefIterator::new(self.metric_layout(), content)?
+      .map(|entry| entry.map(|e| e.to_entry()))
+      .collect()
+  }
+}
 
-    let offset = usize::from_slice(&content[0x54 .. 0x58]);
-    let n = usize::from_slice(&content[0x58 .. 0x5c]);
-    let mut entries = std::vec::Vec::<MetricEntry>::with_capacity(n);
-    let entry_size = 32;
-    let section = &content[offset .. offset + n * entry_size];
-    let name_section_offset = usize::from_slice(&content[0x64 .. 0x68]);
-    let name_section_length = usize::from_slice(&content[0x68 .. 0x6c]);
-    let name_section = &content[name_section_offset .. name_section_offset +
-      name_section_length];
-
-    // Each entry is 20 bytes
-    for i in 0 .. n {
-      let entry = &section[entry_size * i .. entry_size * (i + 1)];
-
-      let name_offset = usize::from_slice(&entry[0xc .. 0x10]);
-      let name_length = usize::from_slice(&entry[0x10 .. 0x14]);
-
-
-      entries.push(MetricEntry {
-        id: i,
-        start_time: Some(u32::from_slice(&entry[0x0 .. 0x4])),
-        duration: Some(u32::from_slice(&entry[0x4 .. 0x8])),
-        average_duration: Some(u32::from_slice(&entry[0x8 .. 0xc])),
-        filename: super::util::fetch_unicode_string(&name_section, name_offset,
-        name_length),
-        mft_entry_index: Some(u64::from_slice(&entry[0x18 .. 0x1e])),
-        sequence_number: Some(u32::from_slice(&entry[0x1e .. 0x20]) as u16)
-      });
-    }
+impl MetricParser for super::parser::WindowsXp2003 {
 
-    Ok(entries)
+  fn metric_layout(&self) -> &'static MetricLayout {
+    &WINDOWS_XP_2003
   }
 
 }
+
+impl MetricParser for super::parser::WindowsVista7 {}
+impl MetricParser for super::parser::Windows8 {}
+impl MetricParser for super::parser::Windows10 {}
+impl MetricParser for super::parser::Windows11 {}
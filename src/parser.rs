@@ -17,73 +17,198 @@
 //          jasa <jan.starke (0x40) t-systems.com>
 
 use std;
-use super::util::FromSlice;
+use super::error::Section;
+use super::filetime::FileTime;
+use super::util::{FromSlice, read};
 
+/// The parsed content of a Prefetch file.
+///
+/// The run times and the run counter are read up front, the sections are
+/// parsed on first access and then cached, once for all threads.
 pub(crate) struct ParserResult {
-  pub(crate) last_execution_time: u64,
+  content: std::vec::Vec<u8>,
+  parser: Box<dyn Parser>,
+  diagnostics: fn(&super::diagnostics::Event),
+  pub(crate) last_execution_times: std::vec::Vec<FileTime>,
   pub(crate) execution_counter: usize,
-  pub(crate) metrics:
-    super::Result<std::vec::Vec<super::metric::MetricEntry>>,
-  pub(crate) trace:
-    super::Result<std::vec::Vec<super::trace::TraceEntry>>,
-  pub(crate) volumes:
-    super::Result<std::vec::Vec<super::volume::VolumeEntry>>
+  metrics: std::sync::OnceLock<
+    super::Result<std::vec::Vec<super::metric::MetricEntry>>>,
+  trace: std::sync::OnceLock<
+    super::Result<std::vec::Vec<super::trace::TraceEntry>>>,
+  volumes: std::sync::OnceLock<
+    super::Result<std::vec::Vec<super::volume::VolumeEntry>>>
 }
 
+impl ParserResult {
+
+  /// Reads the run times and the run counter of `content`. Sections which
+  /// fail to parse later on are reported to `diagnostics`.
+  pub(crate) fn new(parser: Box<dyn Parser>, content: std::vec::Vec<u8>,
+      diagnostics: fn(&super::diagnostics::Event))
+      -> super::Result<ParserResult> {
+    Ok(ParserResult {
+      last_execution_times: parser.last_execution_times(&content)?,
+      execution_counter: parser.execution_counter(&content)?,
+      content,
+      parser,
+      diagnostics,
+      metrics: std::sync::OnceLock::new(),
+      trace: std::sync::OnceLock::new(),
+      volumes: std::sync::OnceLock::new()
+    })
+  }
+
+  /// Builds a result from sections which were already parsed.
+  #[cfg(feature = "serde")]
+  pub(crate) fn from_sections(parser: Box<dyn Parser>,
+      last_execution_times: std::vec::Vec<FileTime>, execution_counter: usize,
+      metrics: super::Result<std::vec::Vec<super::metric::MetricEntry>>,
+      trace: super::Result<std::vec::Vec<super::trace::TraceEntry>>,
+      volumes: super::Result<std::vec::Vec<super::volume::VolumeEntry>>)
+      -> ParserResult {
+    ParserResult {
+      content: std::vec::Vec::new(),
+      parser,
+      diagnostics: |_| (),
+      last_execution_times,
+      execution_counter,
+      metrics: std::sync::OnceLock::from(metrics),
+      trace: std::sync::OnceLock::from(trace),
+      volumes: std::sync::OnceLock::from(volumes)
+    }
+  }
+
+  fn report<T>(&self, section: Section, result: super::Result<T>)
+      -> super::Result<T> {
+    if let Err(ref e) = result {
+      (self.diagnostics)(&super::diagnostics::Event::SectionSkipped(section, e));
+    }
+    result
+  }
+
+  pub(crate) fn metrics(&self)
+      -> &super::Result<std::vec::Vec<super::metric::MetricEntry>> {
+    self.metrics.get_or_init(|| self.report(Section::Metrics,
+      self.parser.parse_metrics(&self.content)))
+  }
+
+  pub(crate) fn trace(&self)
+      -> &super::Result<std::vec::Vec<super::trace::TraceEntry>> {
+    self.trace.get_or_init(|| self.report(Section::TraceChain,
+      self.parser.parse_trace(&self.content)))
+  }
+
+  pub(crate) fn volumes(&self)
+      -> &super::Result<std::vec::Vec<super::volume::VolumeEntry>> {
+    self.volumes.get_or_init(|| self.report(Section::Volumes,
+      self.parser.parse_volumes(&self.content)))
+  }
+}
+
+// Parsers are stateless, they are shared between threads along with the
+// `Prefetch` and `PrefetchRef` which hold them.
 pub(crate) trait Parser: super::metric::MetricParser
   + super::trace::TraceParser
-  + super::volume::VolumeParser {
+  + super::volume::VolumeParser
+  + Send + Sync {
+
+  fn last_execution_times(&self, content: &[u8])
+    -> super::Result<std::vec::Vec<FileTime>>;
+
+  fn execution_counter(&self, content: &[u8]) -> super::Result<usize>;
+
+  /// Returns the bounds of the sections listed in the file information, as
+  /// (section, offset, length).
+  fn sections(&self, content: &[u8])
+      -> super::Result<std::vec::Vec<(Section, usize, usize)>> {
+    let info = |offset| read::<usize>(content, Section::FileInformation,
+      offset, 4);
+    Ok(vec![
+      (Section::Metrics, info(0x54)?,
+        info(0x58)?.saturating_mul(self.metric_entry_size())),
+      (Section::TraceChain, info(0x5c)?,
+        info(0x60)?.saturating_mul(self.trace_entry_size())),
+      (Section::Filenames, info(0x64)?, info(0x68)?),
+      (Section::Volumes, info(0x6c)?, info(0x74)?)
+    ])
+  }
+}
 
-  fn parse(&self, content: &[u8]) -> super::Result<ParserResult>;
+// Starting with Windows 8, up to eight run times are stored, unused slots are
+// zeroed.
+fn last_execution_times(content: &[u8])
+    -> super::Result<std::vec::Vec<FileTime>> {
+  Ok(super::util::slice(content, Section::FileInformation, 0x80, 0x40)?
+    .chunks(8)
+    .map(|t| FileTime::new(u64::from_slice(t)))
+    .filter(|t| !t.is_zero())
+    .collect())
 }
-use super::metric::MetricParser;
-use super::trace::TraceParser;
-use super::volume::VolumeParser;
 
 pub(crate) struct WindowsXp2003;
 pub(crate) struct WindowsVista7;
 pub(crate) struct Windows8;
 pub(crate) struct Windows10;
+pub(crate) struct Windows11;
 
 
 
 impl Parser for WindowsXp2003 {
-  fn parse(&self, content: &[u8]) -> super::Result<ParserResult> {
-    Ok(ParserResult {
-      last_execution_time: u64::from_slice(&content[0x78 .. 0x80]),
-      execution_counter: usize::from_slice(&content[0x90 .. 0x94]),
-      metrics: self.parse_metrics(content),
-      trace: self.parse_trace(content),
-      volumes: self.parse_volumes(content)
-    })
+  fn last_execution_times(&self, content: &[u8])
+      -> super::Result<std::vec::Vec<FileTime>> {
+    let time = FileTime::new(read(content, Section::FileInformation, 0x78, 8)?);
+    Ok(if time.is_zero() { vec![] } else { vec![time] })
   }
 
+  fn execution_counter(&self, content: &[u8]) -> super::Result<usize> {
+    read(content, Section::FileInformation, 0x90, 4)
+  }
 }
 impl Parser for WindowsVista7 {
-  fn parse(&self, content: &[u8]) -> super::Result<ParserResult> {
-    Ok(ParserResult {
-      last_execution_time: u64::from_slice(&content[0x80 .. 0x88]),
-      execution_counter: usize::from_slice(&content[0x98 .. 0x9c]),
-      metrics: self.parse_metrics(content),
-      trace: self.parse_trace(content),
-      volumes: self.parse_volumes(content)
-    })
+  fn last_execution_times(&self, content: &[u8])
+      -> super::Result<std::vec::Vec<FileTime>> {
+    let time = FileTime::new(read(content, Section::FileInformation, 0x80, 8)?);
+    Ok(if time.is_zero() { vec![] } else { vec![time] })
   }
 
+  fn execution_counter(&self, content: &[u8]) -> super::Result<usize> {
+    read(content, Section::FileInformation, 0x98, 4)
+  }
 }
 impl Parser for Windows8 {
-  fn parse(&self, content: &[u8]) -> super::Result<ParserResult> {
-    Ok(ParserResult {
-      last_execution_time: u64::from_slice(&content[0x80 .. 0x88]),
-      execution_counter: usize::from_slice(&content[0xd0 .. 0xd4]),
-      metrics: self.parse_metrics(content),
-      trace: self.parse_trace(content),
-      volumes: self.parse_volumes(content)
-    })
+  fn last_execution_times(&self, content: &[u8])
+      -> super::Result<std::vec::Vec<FileTime>> {
+    last_execution_times(content)
+  }
+
+  fn execution_counter(&self, content: &[u8]) -> super::Result<usize> {
+    read(content, Section::FileInformation, 0xd0, 4)
   }
 }
 impl Parser for Windows10 {
-  fn parse(&self, _content: &[u8]) -> super::Result<ParserResult> {
-    Err(super::error::Error::NotImplemented)
+  fn last_execution_times(&self, content: &[u8])
+      -> super::Result<std::vec::Vec<FileTime>> {
+    last_execution_times(content)
+  }
+
+  fn execution_counter(&self, content: &[u8]) -> super::Result<usize> {
+    // Some Windows 10 files have a file information block which is 8 bytes
+    // shorter, the run counter is then stored right after the run times.
+    match read(content, Section::FileInformation, 0x54, 4)? {
+      0x130 => read(content, Section::FileInformation, 0xc8, 4),
+      _ => read(content, Section::FileInformation, 0xd0, 4)
+    }
+  }
+}
+
+// Windows 11 only bumped the version, all sections keep the Windows 10 layout.
+impl Parser for Windows11 {
+  fn last_execution_times(&self, content: &[u8])
+      -> super::Result<std::vec::Vec<FileTime>> {
+    Windows10.last_execution_times(content)
+  }
+
+  fn execution_counter(&self, content: &[u8]) -> super::Result<usize> {
+    Windows10.execution_counter(content)
   }
 }
@@ -20,6 +20,7 @@ use std;
 
 /// Format versions for a Prefetch file.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FormatVersion {
 
   /// Windows XP and Windows 2003 (code 0x11)
@@ -31,32 +32,59 @@ pub enum FormatVersion {
   /// Windows 8 and Windows 8.1 (code 0x1a)
   Windows8,
 
-  /// Windows 10 (code 0x1e) (not fully supported yet)
-  Windows10
+  /// Windows 10 (code 0x1e)
+  Windows10,
+
+  /// Windows 11 (code 0x1f)
+  Windows11
 }
 
 impl FormatVersion {
 
   pub(crate) fn new(value: u32)
       -> super::Result<(FormatVersion, Box<dyn super::parser::Parser>)> {
-    match value {
-      super::constants::FORMAT_WINDOWS_XP_2003
-        => Ok((FormatVersion::WindowsXp2003,
-        Box::new(super::parser::WindowsXp2003{}))),
-
-      super::constants::FORMAT_WINDOWS_VISTA_7
-        => Ok((FormatVersion::WindowsVista7,
-        Box::new(super::parser::WindowsVista7{}))),
+    let version = match value {
+      super::constants::FORMAT_WINDOWS_XP_2003 => FormatVersion::WindowsXp2003,
+      super::constants::FORMAT_WINDOWS_VISTA_7 => FormatVersion::WindowsVista7,
+      super::constants::FORMAT_WINDOWS_8 => FormatVersion::Windows8,
+      super::constants::FORMAT_WINDOWS_10 => FormatVersion::Windows10,
+      super::constants::FORMAT_WINDOWS_11 => FormatVersion::Windows11,
+      _ => return Err(super::error::Error::UnknownFormatVersion(value))
+    };
+    Ok((version, version.parser()))
+  }
 
-      super::constants::FORMAT_WINDOWS_8
-        => Ok((FormatVersion::Windows8,
-        Box::new(super::parser::Windows8{}))),
+  pub(crate) fn parser(self) -> Box<dyn super::parser::Parser> {
+    match self {
+      FormatVersion::WindowsXp2003 => Box::new(super::parser::WindowsXp2003{}),
+      FormatVersion::WindowsVista7 => Box::new(super::parser::WindowsVista7{}),
+      FormatVersion::Windows8 => Box::new(super::parser::Windows8{}),
+      FormatVersion::Windows10 => Box::new(super::parser::Windows10{}),
+      FormatVersion::Windows11 => Box::new(super::parser::Windows11{})
+    }
+  }
 
-      super::constants::FORMAT_WINDOWS_10
-        => Ok((FormatVersion::Windows10,
-        Box::new(super::parser::Windows10{}))),
+  /// Returns the offset of the end of the file information block.
+  pub(crate) fn file_information_end(self) -> usize {
+    match self {
+      FormatVersion::WindowsXp2003 =>
+        super::constants::FILE_INFORMATION_END_XP_2003,
+      FormatVersion::WindowsVista7 =>
+        super::constants::FILE_INFORMATION_END_VISTA_7,
+      FormatVersion::Windows8 => super::constants::FILE_INFORMATION_END_WINDOWS_8,
+      FormatVersion::Windows10 | FormatVersion::Windows11 =>
+        super::constants::FILE_INFORMATION_END_WINDOWS_10
+    }
+  }
 
-      _ => Err(super::error::Error::UnknownFormatVersion(value))
+  /// Returns the code of the version, as stored in the header.
+  pub fn value(&self) -> u32 {
+    match *self {
+      FormatVersion::WindowsXp2003 => super::constants::FORMAT_WINDOWS_XP_2003,
+      FormatVersion::WindowsVista7 => super::constants::FORMAT_WINDOWS_VISTA_7,
+      FormatVersion::Windows8 => super::constants::FORMAT_WINDOWS_8,
+      FormatVersion::Windows10 => super::constants::FORMAT_WINDOWS_10,
+      FormatVersion::Windows11 => super::constants::FORMAT_WINDOWS_11
     }
   }
 }
@@ -68,7 +96,8 @@ impl std::fmt::Display for FormatVersion {
       FormatVersion::WindowsXp2003 => write!(f, "Windows XP or 2003"),
       FormatVersion::WindowsVista7 => write!(f, "Windows Vista or 7"),
       FormatVersion::Windows8 => write!(f, "Windows 8 or 8.1"),
-      FormatVersion::Windows10 => write!(f, "Windows 10")
+      FormatVersion::Windows10 => write!(f, "Windows 10"),
+      FormatVersion::Windows11 => write!(f, "Windows 11")
     }
   }
 }
@@ -92,15 +121,19 @@ impl std::fmt::Display for FormatVersion {
 ///      Prefetch::from_path("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
 ///
 /// ```
-
 pub struct Prefetch {
-  header: super::header::Header,
+  pub(crate) header: super::header::Header,
   //parser: Box<super::parser::Parser>,
-  parser_result: super::parser::ParserResult
+  pub(crate) parser_result: super::parser::ParserResult
 }
 
 impl Prefetch {
 
+  /// Returns the header of the Prefetch file.
+  pub fn header(&self) -> &super::header::Header {
+    &self.header
+  }
+
   /// Returns the version of the Prefetch file.
   pub fn version(&self) -> FormatVersion {
     self.header.version
@@ -113,6 +146,11 @@ impl Prefetch {
 
   /// Returns the name of the executable.
   pub fn name(&self) -> &str {
+    self.header.name.as_str()
+  }
+
+  /// Returns the name of the executable, as stored in the file.
+  pub fn name_utf16(&self) -> &super::utf16::Utf16String {
     &self.header.name
   }
 
@@ -121,9 +159,61 @@ impl Prefetch {
     self.header.hash
   }
 
-  /// Returns the last execution time, in FILETIME format
-  pub fn last_execution_time(&self) -> u64 {
-    self.parser_result.last_execution_time
+  /// Checks that `path`, the NT device path of the executable, matches the
+  /// hash of the header, and returns the algorithm which produced it.
+  ///
+  /// `None` means that the file does not belong to `path`: it was renamed,
+  /// planted or the executable ran from somewhere else.
+  pub fn verify_hash(&self, path: &str)
+      -> Option<super::hash::HashAlgorithm> {
+    super::hash::HashAlgorithm::candidates(self.header.version).iter()
+      .cloned()
+      .find(|algorithm| algorithm.hash(path) == self.header.hash)
+  }
+
+  /// Returns the full path of the executable, resolved from the metric
+  /// filenames and the volume directories, with how much it can be trusted.
+  ///
+  /// `None` means that no path has the name of the executable. See the
+  /// `executable` module.
+  pub fn executable_path(&self)
+      -> Option<super::executable::ExecutablePath> {
+    super::executable::resolve(self)
+  }
+
+  /// Rewrites `path` to start with the `\VOLUME{...}` identifier of its
+  /// volume, whether it starts with the device path or with the identifier,
+  /// so that paths of both forms can be compared. `None` means that `path` is
+  /// on none of the volumes, or that they could not be parsed.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use libprefetch::Prefetch;
+  ///
+  /// let prefetch =
+  ///     Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+  ///
+  /// let path = r"\Device\HarddiskVolume2\Windows\notepad.exe";
+  /// assert_eq!(prefetch.normalize_path(path),
+  ///   prefetch.normalize_path(r"\VOLUME{01d3c5f9a6b7c8d0-4a3b2c1d}\WINDOWS\NOTEPAD.EXE"));
+  /// ```
+  pub fn normalize_path(&self, path: &str) -> Option<std::string::String> {
+    self.volumes().ok()?.find_map(|v| v.normalize_path(path))
+  }
+
+  /// Returns the last execution time, zero if none is stored.
+  pub fn last_execution_time(&self) -> super::FileTime {
+    self.parser_result.last_execution_times.first().cloned()
+      .unwrap_or_default()
+  }
+
+  /// Returns all the stored execution times, the most recent first.
+  ///
+  /// Windows XP to 7 only store the last one. Starting with Windows 8, up to
+  /// eight run times are kept. Only the non-zero ones are returned.
+  pub fn last_execution_times(&self) -> &[super::FileTime] {
+    &self.parser_result.last_execution_times
   }
 
   /// Returns the execution counter (how many times the exe was run).
@@ -147,7 +237,7 @@ impl Prefetch {
   /// }
   /// ```
   pub fn metrics(&self)
-      -> super::Result<super::iterator::MetricIterator> {
+      -> super::Result<super::iterator::MetricIterator<'_>> {
     super::iterator::MetricIterator::new(&self.parser_result)
   }
 
@@ -164,11 +254,11 @@ impl Prefetch {
   ///
   /// // Iterate over the chain
   /// for item in prefetch.trace().unwrap() {
-  ///     println!("Trace #{}, bytes loaded:: {}", item.id(), item.load_count());
+  ///     println!("Trace #{}, blocks loaded: {}", item.id(), item.load_count());
   /// }
   /// ```
   pub fn trace(&self)
-      -> super::Result<super::iterator::TraceIterator> {
+      -> super::Result<super::iterator::TraceIterator<'_>> {
     super::iterator::TraceIterator::new(&self.parser_result)
   }
 
@@ -197,12 +287,16 @@ impl Prefetch {
   ///
   /// ```
   pub fn volumes(&self)
-      -> super::Result<super::iterator::VolumeIterator> {
+      -> super::Result<super::iterator::VolumeIterator<'_>> {
     super::iterator::VolumeIterator::new(&self.parser_result)
   }
 
   /// Constructs a new `Prefetch` from a `std::io::Read` source.
   ///
+  /// Only the header, the run times and the run counter are read right away:
+  /// metrics, trace chain and volumes are parsed on first access. With the
+  /// `log` feature, the parsing events are logged, see
+  /// `Prefetch::with_diagnostics`.
   ///
   /// # Example
   ///
@@ -214,35 +308,211 @@ impl Prefetch {
   /// let prefetch = Prefetch::new(file).unwrap();
   ///
   /// ```
-  pub fn new<T>(mut src: T) -> super::Result<Prefetch>
+  pub fn new<T>(src: T) -> super::Result<Prefetch>
     where T: std::io::Read {
+    Prefetch::load(src, &mut super::diagnostics::log, super::diagnostics::log)
+  }
+
+  /// Constructs a new `Prefetch` from a `std::io::Read` source, handing the
+  /// parsing events to `diagnostics`.
+  ///
+  /// Unlike `Prefetch::new`, all the sections are parsed right away so that
+  /// the skipped ones are reported.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use libprefetch::Prefetch;
+  /// use libprefetch::diagnostics::Event;
+  ///
+  /// let file = std::fs::File::open("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+  ///
+  /// let mut sections = 0;
+  /// let prefetch = Prefetch::with_diagnostics(file, |event: &Event| {
+  ///   if let Event::SectionBounds(..) = *event {
+  ///     sections += 1;
+  ///   }
+  /// }).unwrap();
+  /// assert_eq!(4, sections);
+  /// ```
+  pub fn with_diagnostics<T, D>(src: T, mut diagnostics: D)
+      -> super::Result<Prefetch>
+    where T: std::io::Read, D: FnMut(&super::diagnostics::Event) {
+      use super::diagnostics::Event;
+      use super::error::Section;
+
+      let prefetch = Prefetch::load(src, &mut diagnostics, |_| ())?;
+      let result = &prefetch.parser_result;
+      if let Err(ref e) = *result.metrics() {
+        diagnostics(&Event::SectionSkipped(Section::Metrics, e));
+      }
+      if let Err(ref e) = *result.trace() {
+        diagnostics(&Event::SectionSkipped(Section::TraceChain, e));
+      }
+      if let Err(ref e) = *result.volumes() {
+        diagnostics(&Event::SectionSkipped(Section::Volumes, e));
+      }
+    Ok(prefetch)
+  }
+
+  /// Reads the data and the header, the sections failing later on are
+  /// reported to `skipped`.
+  fn load<T, D>(mut src: T, diagnostics: &mut D,
+      skipped: fn(&super::diagnostics::Event)) -> super::Result<Prefetch>
+    where T: std::io::Read, D: FnMut(&super::diagnostics::Event) {
       let mut buf = Vec::new();
       src.read_to_end(&mut buf).map_err(super::error::Error::IOError)?;
-      if &buf[0..4] == b"MAM\x04" {
-
-        let uncompressed_length = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
-        println!("uncompressed length: {:08x}", uncompressed_length);
+      Prefetch::from_buffer(buf, diagnostics, skipped)
+  }
 
-        buf = match lzxpress::data::decompress(&buf[8..]) {
-          Ok(b) => b,
-          Err(e)  => return Err(super::error::Error::LZXPressError(e))
-        };
+  /// Parses the data of a Prefetch file, decompressing it first if needed.
+  fn from_buffer<D>(buf: std::vec::Vec<u8>, diagnostics: &mut D,
+      skipped: fn(&super::diagnostics::Event)) -> super::Result<Prefetch>
+    where D: FnMut(&super::diagnostics::Event) {
+      if super::compression::is_compressed(&buf) {
+        let decompressed = super::compression::decompress_mam(&buf)?;
+        return Prefetch::from_decompressed(&buf, decompressed, diagnostics,
+          skipped);
       }
+      Prefetch::from_data(buf, diagnostics, skipped)
+  }
+
+  /// Parses the data decompressed from the `compressed` file.
+  fn from_decompressed<D>(compressed: &[u8], buf: std::vec::Vec<u8>,
+      diagnostics: &mut D, skipped: fn(&super::diagnostics::Event))
+      -> super::Result<Prefetch>
+    where D: FnMut(&super::diagnostics::Event) {
+      diagnostics(&super::diagnostics::Event::Decompressed(compressed.len(),
+        buf.len(), super::compression::has_checksum(compressed)));
+      Prefetch::from_data(buf, diagnostics, skipped)
+  }
 
-      let header = &buf[0..super::constants::HEADER_LENGTH];
-      println!("header length: {}", header.len());
+  /// Parses the uncompressed data of a Prefetch file.
+  fn from_data<D>(buf: std::vec::Vec<u8>, diagnostics: &mut D,
+      skipped: fn(&super::diagnostics::Event)) -> super::Result<Prefetch>
+    where D: FnMut(&super::diagnostics::Event) {
+      use super::diagnostics::Event;
+      use super::error::Section;
+
+      let header = super::util::slice(&buf, Section::Header, 0,
+        super::constants::HEADER_LENGTH)?;
 
       let (header, parser) = super::header::Header::new(header)?;
+      diagnostics(&Event::VersionDetected(header.version));
+      if header.size != buf.len() {
+        diagnostics(&Event::SizeMismatch(header.size, buf.len()));
+      }
+      if let Ok(sections) = parser.sections(&buf) {
+        for (section, offset, length) in sections {
+          diagnostics(&Event::SectionBounds(section, offset, length));
+        }
+      }
 
-      let data = &buf[..];
-      println!("data length: {}", data.len());
-      let result = parser.parse(&data)?;
     Ok(Prefetch {
-      header: header,
-      parser_result: result
+      header,
+      parser_result: super::parser::ParserResult::new(parser, buf, skipped)?
     })
   }
 
+  /// Constructs a new `Prefetch` from a file starting at `offset` of a
+  /// `std::io::Read` and `std::io::Seek` source, such as a disk image.
+  ///
+  /// The header is read first, then the file information and the sections it
+  /// lists, never past the size announced by the header. Compressed files do
+  /// not store their compressed size: they are read and decompressed in
+  /// chunks until their compressed data is complete, a chunk may go past its
+  /// end.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use libprefetch::Prefetch;
+  ///
+  /// let file = std::fs::File::open("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+  ///
+  /// let prefetch = Prefetch::from_reader_at(file, 0).unwrap();
+  /// assert_eq!("WUAUCLT.EXE", prefetch.name());
+  /// ```
+  pub fn from_reader_at<T>(mut src: T, offset: u64) -> super::Result<Prefetch>
+    where T: std::io::Read + std::io::Seek {
+      use std::io::{Read, SeekFrom};
+      use super::error::Error;
+
+      src.seek(SeekFrom::Start(offset)).map_err(Error::IOError)?;
+      let mut buf = Vec::new();
+      (&mut src).take(super::constants::HEADER_LENGTH as u64)
+        .read_to_end(&mut buf).map_err(Error::IOError)?;
+      if !super::compression::is_compressed(&buf) {
+        let header = super::util::slice(&buf, super::error::Section::Header, 0,
+          super::constants::HEADER_LENGTH)?;
+        let (header, parser) = super::header::Header::new(header)?;
+
+        // The size comes from the file, do not trust it to reserve memory.
+        let end = src.seek(SeekFrom::End(0)).map_err(Error::IOError)?;
+        let size = std::cmp::min(header.size as u64, end.saturating_sub(offset))
+          as usize;
+        buf.resize(size, 0);
+
+        let mut read_at = |buf: &mut [u8], start: usize, end: usize| {
+          let end = std::cmp::min(end, size);
+          if start >= end {
+            return Ok(());
+          }
+          src.seek(SeekFrom::Start(offset + start as u64))
+            .and_then(|_| src.read_exact(&mut buf[start .. end]))
+            .map_err(Error::IOError)
+        };
+        read_at(&mut buf, super::constants::HEADER_LENGTH,
+          header.version.file_information_end())?;
+        // A corrupted file information is reported when parsing.
+        if let Ok(sections) = parser.sections(&buf) {
+          for (_, start, length) in sections {
+            read_at(&mut buf, start, start.saturating_add(length))?;
+          }
+        }
+      } else {
+        let decompressed = read_compressed(&mut src, &mut buf)?;
+        return Prefetch::from_decompressed(&buf, decompressed,
+          &mut super::diagnostics::log, super::diagnostics::log);
+      }
+
+      Prefetch::from_data(buf, &mut super::diagnostics::log,
+        super::diagnostics::log)
+  }
+
+  /// Reads only the header of a Prefetch file from a `std::io::Read` source.
+  ///
+  /// This is a fast path to index many files: only the first bytes are read,
+  /// unless the file is compressed. Compressed files are read in chunks up to
+  /// the end of their compressed data.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use libprefetch::Prefetch;
+  ///
+  /// let file = std::fs::File::open("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+  ///
+  /// let header = Prefetch::read_header(file).unwrap();
+  /// assert_eq!("WUAUCLT.EXE", header.name());
+  /// ```
+  pub fn read_header<T>(src: T) -> super::Result<super::header::Header>
+    where T: std::io::Read {
+      use std::io::Read;
+
+      let mut src = src;
+      let mut buf = Vec::new();
+      (&mut src).take(super::constants::HEADER_LENGTH as u64)
+        .read_to_end(&mut buf).map_err(super::error::Error::IOError)?;
+      if super::compression::is_compressed(&buf) {
+        buf = read_compressed(&mut src, &mut buf)?;
+      }
+
+      let header = super::util::slice(&buf, super::error::Section::Header, 0,
+        super::constants::HEADER_LENGTH)?;
+      super::header::Header::new(header).map(|(header, _)| header)
+  }
+
   /// Constructs a new `Prefetch` from a file path.
   ///
   ///
@@ -260,3 +530,31 @@ impl Prefetch {
     Prefetch::new(f)
   }
 }
+
+/// Reads the rest of a `MAM` compressed file whose first bytes are in `buf`,
+/// in chunks of growing size, decompressing each as it comes until the
+/// compressed stream is complete. Returns the decompressed data, `buf` keeps
+/// the compressed file: the bytes read past its end are dropped.
+fn read_compressed<T>(src: &mut T, buf: &mut std::vec::Vec<u8>)
+    -> super::Result<std::vec::Vec<u8>>
+  where T: std::io::Read {
+  use std::io::Read;
+
+  let mut decoder = super::compression::MamDecoder::new();
+  let mut chunk = 0x10000;
+  let mut complete = false;
+  loop {
+    if let Some(length) = decoder.decode(buf, complete)? {
+      buf.truncate(length);
+      return Ok(decoder.into_output());
+    }
+    let read = (&mut *src).take(chunk).read_to_end(buf)
+      .map_err(super::error::Error::IOError)?;
+    complete = read == 0;
+    chunk = chunk.saturating_mul(2);
+  }
+}
+
+// Files are commonly parsed on a thread pool: the sections parsed on first
+// access must not make `Prefetch` thread-bound.
+const _: fn() = super::util::assert_send_sync::<Prefetch>;
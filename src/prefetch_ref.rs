@@ -0,0 +1,149 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+use std;
+
+/// A borrowed view over the data of a Prefetch file.
+///
+/// Unlike `Prefetch`, nothing is copied nor decoded up front: the entries are
+/// read from the slice when iterated. Only compressed files are decompressed
+/// into an owned buffer.
+///
+/// # Example
+///
+/// ```
+/// use libprefetch::PrefetchRef;
+///
+/// let data = std::fs::read("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+/// let prefetch = PrefetchRef::new(&data).unwrap();
+///
+/// assert_eq!("WUAUCLT.EXE", prefetch.name().to_string_lossy());
+/// for metric in prefetch.metrics().unwrap() {
+///   println!("Loaded file by the exe: {}", metric.unwrap().filename());
+/// }
+/// ```
+pub struct PrefetchRef<'a> {
+  data: std::borrow::Cow<'a, [u8]>,
+  header: super::header::Header,
+  parser: Box<dyn super::parser::Parser>
+}
+
+impl<'a> PrefetchRef<'a> {
+
+  /// Constructs a new `PrefetchRef` over the data of a Prefetch file.
+  ///
+  /// Compressed files are decompressed into an owned buffer.
+  pub fn new(data: &'a [u8]) -> super::Result<PrefetchRef<'a>> {
+    let data = if super::compression::is_compressed(data) {
+      std::borrow::Cow::Owned(super::compression::decompress_mam(data)?)
+    } else {
+      std::borrow::Cow::Borrowed(data)
+    };
+    let header = super::util::slice(&data, super::error::Section::Header, 0,
+      super::constants::HEADER_LENGTH)?;
+    let (header, parser) = super::header::Header::new(header)?;
+
+    Ok(PrefetchRef {
+      data,
+      header,
+      parser
+    })
+  }
+
+  /// Returns whether the view borrows the data, that is whether the file was
+  /// not compressed.
+  pub fn is_borrowed(&self) -> bool {
+    match self.data {
+      std::borrow::Cow::Borrowed(_) => true,
+      std::borrow::Cow::Owned(_) => false
+    }
+  }
+
+  /// Returns the version of the Prefetch file.
+  pub fn version(&self) -> super::FormatVersion {
+    self.header.version
+  }
+
+  /// Returns the size of the Prefetch file.
+  pub fn size(&self) -> usize {
+    self.header.size
+  }
+
+  /// Returns the name of the executable.
+  pub fn name(&self) -> super::utf16::Utf16Str<'_> {
+    let limit = (super::constants::MAX_FILENAME_LENGTH + 1) * 2;
+    super::utf16::Utf16Str::from_bytes(&self.data[0x10 .. 0x10 + limit])
+  }
+
+  /// Returns the prefetch hash of the executable.
+  pub fn hash(&self) -> u32 {
+    self.header.hash
+  }
+
+  /// Returns all the stored execution times, the most recent first.
+  pub fn last_execution_times(&self)
+      -> super::Result<std::vec::Vec<super::FileTime>> {
+    self.parser.last_execution_times(&self.data)
+  }
+
+  /// Returns the execution counter (how many times the exe was run).
+  pub fn execution_counter(&self) -> super::Result<usize> {
+    self.parser.execution_counter(&self.data)
+  }
+
+  /// Returns an Iterator for file metrics.
+  pub fn metrics(&self)
+      -> super::Result<super::iterator::MetricRefIterator<'_>> {
+    super::iterator::MetricRefIterator::new(self.parser.metric_layout(),
+      &self.data)
+  }
+
+  /// Returns an Iterator for the trace chain.
+  pub fn trace(&self)
+      -> super::Result<super::iterator::TraceRefIterator<'_>> {
+    super::iterator::TraceRefIterator::new(self.parser.trace_layout(),
+      &self.data)
+  }
+
+  /// Returns the trace chain entries which belong to a file metric.
+  pub fn trace_entries(&self, metric: &super::metric::MetricEntryRef)
+      -> super::Result<super::iterator::TraceRefIterator<'_>> {
+    let layout = self.parser.trace_layout();
+    let trace = layout.section(&self.data)?;
+    let entry_size = layout.entry_size();
+    metric.trace_chain_index().checked_add(metric.trace_entry_count())
+      .and_then(|end| end.checked_mul(entry_size))
+      .and_then(|end| trace.get(metric.trace_chain_index() * entry_size .. end))
+      .map(|entries| super::iterator::TraceRefIterator::from_entries(layout,
+        entries, metric.trace_chain_index()))
+      .ok_or(super::error::Error::EntryOutOfBounds(
+        super::error::Section::TraceChain, metric.trace_chain_index(),
+        metric.trace_entry_count()))
+  }
+
+  /// Returns an Iterator for the volumes.
+  pub fn volumes(&self)
+      -> super::Result<super::iterator::VolumeRefIterator<'_>> {
+    super::iterator::VolumeRefIterator::new(self.parser.volume_layout(),
+      &self.data)
+  }
+}
+
+// The view is shared between threads like the data it borrows.
+const _: fn() = super::util::assert_send_sync::<PrefetchRef<'static>>;
@@ -0,0 +1,230 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+//! A minimal reader of registry hive files (regf), enough to list the values
+//! of a key.
+
+use std;
+use super::error::Error;
+use super::util::FromSlice;
+
+/// Size of the base block, the first hive bin follows.
+const BASE_BLOCK_LENGTH: usize = 0x1000;
+
+/// Largest data stored in a single cell, bigger data is split in segments.
+const BIG_DATA_SEGMENT_LENGTH: usize = 16344;
+
+/// Name of a key or of a value stored as Latin-1 rather than UTF-16.
+const KEY_COMP_NAME: u16 = 0x20;
+const VALUE_COMP_NAME: u16 = 0x1;
+
+/// A registry hive, kept in memory.
+pub(crate) struct Hive {
+  data: std::vec::Vec<u8>,
+  root: usize
+}
+
+/// A value of a key: its name and data.
+pub(crate) struct Value {
+  pub(crate) name: std::string::String,
+  pub(crate) data: std::vec::Vec<u8>
+}
+
+impl Hive {
+
+  pub(crate) fn new(data: std::vec::Vec<u8>) -> super::Result<Hive> {
+    if data.len() < BASE_BLOCK_LENGTH || &data[.. 4] != b"regf" {
+      return Err(Error::InvalidHive(0));
+    }
+    let root = u32::from_slice(&data[0x24 .. 0x28]) as usize;
+    let hive = Hive { data, root };
+    hive.record(hive.root, b"nk")?;
+    Ok(hive)
+  }
+
+  /// Returns the values of the key at `path`, whose components are separated
+  /// by backslashes and compared without case. `None` means that there is no
+  /// such key.
+  pub(crate) fn values(&self, path: &str)
+      -> super::Result<Option<std::vec::Vec<Value>>> {
+    let mut key = self.root;
+    for name in path.split('\\').filter(|n| !n.is_empty()) {
+      key = match self.subkey(key, name)? {
+        Some(k) => k,
+        None => return Ok(None)
+      };
+    }
+    let nk = self.record(key, b"nk")?;
+    let count = self.u32(nk, 0x24)? as usize;
+    if count == 0 {
+      return Ok(Some(std::vec::Vec::new()));
+    }
+    // The count comes from the hive, the list must hold it before reserving
+    // memory for it.
+    let list = self.u32(nk, 0x28)? as usize;
+    let available = self.cell_length(list)?;
+    if count.checked_mul(4).is_none_or(|length| length > available) {
+      return Err(Error::InvalidHive(BASE_BLOCK_LENGTH + list));
+    }
+    let list = self.cell(list)?;
+    let mut values = std::vec::Vec::with_capacity(count);
+    for i in 0 .. count {
+      values.push(self.value(self.u32(list, i * 4)? as usize)?);
+    }
+    Ok(Some(values))
+  }
+
+  fn subkey(&self, key: usize, name: &str) -> super::Result<Option<usize>> {
+    let nk = self.record(key, b"nk")?;
+    if self.u32(nk, 0x14)? == 0 {
+      return Ok(None);
+    }
+    let mut found = None;
+    self.walk_subkeys(self.u32(nk, 0x1c)? as usize, &mut |offset| {
+      if found.is_none() && self.key_name(offset)?.eq_ignore_ascii_case(name) {
+        found = Some(offset);
+      }
+      Ok(())
+    }, 0)?;
+    Ok(found)
+  }
+
+  // Index roots ("ri") point to other lists, they are not nested further by
+  // Windows: the depth is bounded to guard against loops.
+  fn walk_subkeys(&self, list: usize,
+      f: &mut dyn FnMut(usize) -> super::Result<()>, depth: usize)
+      -> super::Result<()> {
+    let cell = self.cell(list)?;
+    let count = u16::from_slice(self.slice(cell + 2, 2)?) as usize;
+    match self.slice(cell, 2)? {
+      b"lf" | b"lh" => for i in 0 .. count {
+        f(self.u32(cell, 4 + i * 8)? as usize)?;
+      },
+      b"li" => for i in 0 .. count {
+        f(self.u32(cell, 4 + i * 4)? as usize)?;
+      },
+      b"ri" if depth == 0 => for i in 0 .. count {
+        self.walk_subkeys(self.u32(cell, 4 + i * 4)? as usize, f, depth + 1)?;
+      },
+      _ => return Err(Error::InvalidHive(BASE_BLOCK_LENGTH + list))
+    }
+    Ok(())
+  }
+
+  fn key_name(&self, key: usize) -> super::Result<std::string::String> {
+    let nk = self.record(key, b"nk")?;
+    let flags = u16::from_slice(self.slice(nk + 2, 2)?);
+    let length = u16::from_slice(self.slice(nk + 0x48, 2)?) as usize;
+    Ok(decode_name(self.slice(nk + 0x4c, length)?,
+      flags & KEY_COMP_NAME != 0))
+  }
+
+  fn value(&self, offset: usize) -> super::Result<Value> {
+    let vk = self.record(offset, b"vk")?;
+    let length = u16::from_slice(self.slice(vk + 2, 2)?) as usize;
+    let size = self.u32(vk, 4)?;
+    let flags = u16::from_slice(self.slice(vk + 0x10, 2)?);
+    let name = decode_name(self.slice(vk + 0x14, length)?,
+      flags & VALUE_COMP_NAME != 0);
+
+    // Up to four bytes are stored in place of the data offset.
+    let data = if size & 0x8000_0000 != 0 {
+      let size = (size & 0x7fff_ffff) as usize;
+      self.slice(vk + 8, size.min(4))?.to_vec()
+    } else {
+      let size = size as usize;
+      let cell = self.cell(self.u32(vk, 8)? as usize)?;
+      if size > BIG_DATA_SEGMENT_LENGTH && self.slice(cell, 2)? == b"db" {
+        self.big_data(cell, size)?
+      } else {
+        self.slice(cell, size)?.to_vec()
+      }
+    };
+    Ok(Value { name, data })
+  }
+
+  fn big_data(&self, db: usize, size: usize)
+      -> super::Result<std::vec::Vec<u8>> {
+    // Each byte of the data is stored in the hive.
+    if size > self.data.len() {
+      return Err(Error::InvalidHive(db));
+    }
+    let count = u16::from_slice(self.slice(db + 2, 2)?) as usize;
+    let list = self.cell(self.u32(db, 4)? as usize)?;
+    let mut data = std::vec::Vec::with_capacity(size);
+    for i in 0 .. count {
+      let segment = self.cell(self.u32(list, i * 4)? as usize)?;
+      let length = (size - data.len()).min(BIG_DATA_SEGMENT_LENGTH);
+      data.extend_from_slice(self.slice(segment, length)?);
+    }
+    match data.len() == size {
+      true => Ok(data),
+      false => Err(Error::InvalidHive(BASE_BLOCK_LENGTH + db))
+    }
+  }
+
+  /// Returns the absolute offset of the data of the cell at `offset`, which
+  /// is relative to the first hive bin.
+  fn cell(&self, offset: usize) -> super::Result<usize> {
+    let start = BASE_BLOCK_LENGTH.checked_add(offset)
+      .ok_or(Error::InvalidHive(offset))?;
+    // Allocated cells have a negative size.
+    let size = i32::from_slice(self.slice(start, 4)?);
+    let end = start.checked_add(size.unsigned_abs() as usize);
+    match size < 0 && end.is_some_and(|end| end <= self.data.len()) {
+      true => Ok(start + 4),
+      false => Err(Error::InvalidHive(start))
+    }
+  }
+
+  /// Returns the length of the data of the cell at `offset`.
+  fn cell_length(&self, offset: usize) -> super::Result<usize> {
+    let start = self.cell(offset)?;
+    let size = i32::from_slice(&self.data[start - 4 .. start]).unsigned_abs();
+    Ok((size as usize).saturating_sub(4))
+  }
+
+  fn record(&self, offset: usize, signature: &[u8]) -> super::Result<usize> {
+    let cell = self.cell(offset)?;
+    match self.slice(cell, 2)? == signature {
+      true => Ok(cell),
+      false => Err(Error::InvalidHive(cell))
+    }
+  }
+
+  fn slice(&self, offset: usize, length: usize) -> super::Result<&[u8]> {
+    offset.checked_add(length)
+      .and_then(|end| self.data.get(offset .. end))
+      .ok_or(Error::InvalidHive(offset))
+  }
+
+  fn u32(&self, cell: usize, offset: usize) -> super::Result<u32> {
+    Ok(u32::from_slice(self.slice(cell + offset, 4)?))
+  }
+}
+
+fn decode_name(bytes: &[u8], latin1: bool) -> std::string::String {
+  match latin1 {
+    true => bytes.iter().map(|&b| b as char).collect(),
+    false => std::char::decode_utf16(bytes.chunks_exact(2)
+        .map(u16::from_slice))
+      .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+      .collect()
+  }
+}
@@ -0,0 +1,162 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+//! Drive letters of the volumes, read from an offline SYSTEM registry hive.
+//!
+//! The `MountedDevices` key of the SYSTEM hive lists the drive letters
+//! (`\DosDevices\C:`) and the volume GUIDs (`\??\Volume{...}`), each with an
+//! identifier of the volume: a disk signature and a partition offset for MBR
+//! disks, a partition GUID for GPT disks. A `DriveMap` is built from it:
+//!
+//! every volume GUID sharing its identifier with a drive letter is mapped to
+//! that letter.
+//!
+//! The hive does not store the `\DEVICE\HARDDISKVOLUMEn` device paths used by
+//! the Prefetch files: Windows numbers the volumes in the order it finds them
+//! at boot. These paths, and the `\VOLUME{...}` ones of Windows 10, are added
+//! with `DriveMap::insert` when they are known, for example from the serial
+//! numbers of the volumes of the files.
+//!
+//! This module needs the `registry` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use libprefetch::Prefetch;
+//! use libprefetch::registry::DriveMap;
+//!
+//! let mut drives = DriveMap::from_path("assets/SYSTEM").unwrap();
+//! drives.insert(r"\DEVICE\HARDDISKVOLUME2", 'C');
+//!
+//! let prefetch =
+//!     Prefetch::from_path("assets/NOTEPAD.EXE-D8414F97.pf").unwrap();
+//! let executable = prefetch.executable_path().unwrap();
+//! assert_eq!(r"C:\WINDOWS\SYSTEM32\NOTEPAD.EXE",
+//!   drives.rewrite(executable.path()).unwrap());
+//!
+//! for metric in prefetch.metrics().unwrap() {
+//!   println!("{}", drives.rewrite(metric.filename()).unwrap());
+//! }
+//! for volume in prefetch.volumes().unwrap() {
+//!   for directory in volume.directories().unwrap() {
+//!     println!("{}", drives.rewrite(directory).unwrap());
+//!   }
+//! }
+//! ```
+
+use std;
+use std::io::Read;
+use super::error::Error;
+
+/// Prefix of the values naming a drive letter.
+const DOS_DEVICES: &str = r"\DOSDEVICES\";
+
+/// Prefix of the values naming a volume GUID.
+const VOLUME_GUID: &str = r"\??\VOLUME{";
+
+/// A mapping from device paths to drive letters.
+#[derive(Debug, Clone, Default)]
+pub struct DriveMap {
+  devices: std::collections::BTreeMap<std::string::String, char>
+}
+
+impl DriveMap {
+
+  /// Constructs an empty mapping.
+  pub fn new() -> DriveMap {
+    DriveMap::default()
+  }
+
+  /// Builds the mapping from the data of a SYSTEM hive.
+  pub fn from_hive(data: std::vec::Vec<u8>) -> super::Result<DriveMap> {
+    let hive = super::regf::Hive::new(data)?;
+    let values = hive.values("MountedDevices")?
+      .ok_or_else(|| Error::RegistryKeyNotFound("MountedDevices".into()))?;
+
+    let letters: std::vec::Vec<(&[u8], char)> = values.iter()
+      .filter_map(|v| {
+        let name = v.name.to_ascii_uppercase();
+        let letter = name.strip_prefix(DOS_DEVICES)?;
+        let mut chars = letter.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+          (Some(l), Some(':'), None) if l.is_ascii_alphabetic() =>
+            Some((&v.data[..], l)),
+          _ => None
+        }
+      })
+      .collect();
+    let letter = |data: &[u8]| letters.iter()
+      .find(|&&(d, _)| d == data)
+      .map(|&(_, l)| l);
+
+    let mut map = DriveMap::new();
+    for value in &values {
+      let name = value.name.to_ascii_uppercase();
+      if name.starts_with(VOLUME_GUID) {
+        if let Some(l) = letter(&value.data) {
+          map.devices.insert(name, l);
+        }
+      }
+    }
+
+    Ok(map)
+  }
+
+  /// Builds the mapping from a SYSTEM hive file.
+  pub fn from_path(path: &str) -> super::Result<DriveMap> {
+    let mut data = std::vec::Vec::new();
+    std::fs::File::open(path)
+      .and_then(|mut f| f.read_to_end(&mut data))
+      .map_err(Error::IOError)?;
+    DriveMap::from_hive(data)
+  }
+
+  /// Maps `device`, such as `\DEVICE\HARDDISKVOLUME2`, to `letter`.
+  pub fn insert(&mut self, device: &str, letter: char) {
+    self.devices.insert(device.trim_end_matches('\\').to_ascii_uppercase(),
+      letter.to_ascii_uppercase());
+  }
+
+  /// Returns the drive letter of `device`, compared without case.
+  pub fn drive_letter(&self, device: &str) -> Option<char> {
+    self.devices.get(&device.trim_end_matches('\\').to_ascii_uppercase())
+      .cloned()
+  }
+
+  /// Returns an iterator through the devices and their drive letters.
+  pub fn iter(&self) -> impl Iterator<Item = (&str, char)> {
+    self.devices.iter().map(|(d, &l)| (d.as_str(), l))
+  }
+
+  /// Replaces the device at the start of `path` by its drive letter, such as
+  /// `\DEVICE\HARDDISKVOLUME2\WINDOWS` by `C:\WINDOWS`. Returns `None` when
+  /// the device is not mapped.
+  pub fn rewrite(&self, path: &str) -> Option<std::string::String> {
+    // The ASCII upper case keeps the byte offsets of the path.
+    let upper = path.to_ascii_uppercase();
+    self.devices.iter()
+      .filter(|&(device, _)| upper.starts_with(device.as_str())
+        && upper[device.len() ..].chars().next().is_none_or(|c| c == '\\'))
+      .max_by_key(|&(device, _)| device.len())
+      .map(|(device, letter)| match &path[device.len() ..] {
+        "" => format!(r"{}:\", letter),
+        rest => format!("{}:{}", letter, rest)
+      })
+  }
+}
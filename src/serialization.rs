@@ -0,0 +1,107 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+//! Serialization of a parsed Prefetch file, with the `serde` feature.
+//!
+//! A `Prefetch` is serialized as a structure with the following fields:
+//!
+//!   * `header`: the `Header`, with the `version`, `size`, `name` and `hash`
+//!     of the file;
+//!   * `last_execution_times`: the run times, as raw FILETIME values;
+//!   * `execution_counter`;
+//!   * `metrics`, `trace` and `volumes`: the entries of each section, or
+//!     `null` if the section could not be parsed.
+//!
+//! Strings are serialized as an enumeration: a `Str` holding the string when it
+//! is valid UTF-16, and `Units` holding its code units otherwise, such as
+//! `{"Str": "NOTEPAD.EXE"}` in JSON. The layout does not depend on the format,
+//! which may or may not describe its data. Once deserialized, a section which
+//! could not be parsed fails with `Error::SectionUnavailable`.
+//!
+//! # Example
+//!
+//! ```
+//! use libprefetch::Prefetch;
+//!
+//! let prefetch =
+//!     Prefetch::from_path("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+//!
+//! let json = serde_json::to_string(&prefetch).unwrap();
+//! let loaded: Prefetch = serde_json::from_str(&json).unwrap();
+//! assert_eq!(prefetch.metrics().unwrap().count(),
+//!   loaded.metrics().unwrap().count());
+//! ```
+
+use std;
+use super::error::{Error, Section};
+
+#[derive(serde::Serialize)]
+struct SerializedPrefetch<'a> {
+  header: &'a super::header::Header,
+  last_execution_times: &'a [super::FileTime],
+  execution_counter: usize,
+  metrics: Option<&'a [super::metric::MetricEntry]>,
+  trace: Option<&'a [super::trace::TraceEntry]>,
+  volumes: Option<&'a [super::volume::VolumeEntry]>
+}
+
+#[derive(serde::Deserialize)]
+struct DeserializedPrefetch {
+  header: super::header::Header,
+  last_execution_times: std::vec::Vec<super::FileTime>,
+  execution_counter: usize,
+  metrics: Option<std::vec::Vec<super::metric::MetricEntry>>,
+  trace: Option<std::vec::Vec<super::trace::TraceEntry>>,
+  volumes: Option<std::vec::Vec<super::volume::VolumeEntry>>
+}
+
+impl serde::Serialize for super::Prefetch {
+
+  fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where S: serde::Serializer {
+    let result = &self.parser_result;
+    SerializedPrefetch {
+      header: &self.header,
+      last_execution_times: &result.last_execution_times,
+      execution_counter: result.execution_counter,
+      metrics: result.metrics().as_ref().ok().map(|v| &v[..]),
+      trace: result.trace().as_ref().ok().map(|v| &v[..]),
+      volumes: result.volumes().as_ref().ok().map(|v| &v[..])
+    }.serialize(serializer)
+  }
+}
+
+impl<'de> serde::Deserialize<'de> for super::Prefetch {
+
+  fn deserialize<D>(deserializer: D)
+      -> std::result::Result<super::Prefetch, D::Error>
+    where D: serde::Deserializer<'de> {
+    let p = DeserializedPrefetch::deserialize(deserializer)?;
+    Ok(super::Prefetch {
+      parser_result: super::parser::ParserResult::from_sections(
+        p.header.version.parser(),
+        p.last_execution_times,
+        p.execution_counter,
+        p.metrics.ok_or(Error::SectionUnavailable(Section::Metrics)),
+        p.trace.ok_or(Error::SectionUnavailable(Section::TraceChain)),
+        p.volumes.ok_or(Error::SectionUnavailable(Section::Volumes))),
+      header: p.header
+    })
+  }
+}
@@ -17,19 +17,66 @@
 //          jasa <jan.starke (0x40) t-systems.com>
 
 use std;
-use super::util::FromSlice;
+use super::error::Section;
+use super::util::{FromSlice, read};
+
+/// Layout of the trace chain entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TraceLayout {
+
+  /// Windows XP to Windows 8.1: 12 bytes entries, linked together by the
+  /// index of the next entry.
+  Chained,
+
+  /// Windows 10 and later: 8 bytes entries, stored one after the other
+  /// without any link.
+  Flat
+}
 
 /// An entry for the trace chain.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TraceEntry {
   id: usize,
+  layout: TraceLayout,
   next_entry_id: Option<usize>,
-  load_count: usize
+  load_count: usize,
+  flags: u8,
+  sample_duration: u8
 }
 
 impl TraceEntry {
 
+  /// Constructs an entry of the `TraceLayout::Chained` layout.
+  pub fn chained(id: usize, next_entry_id: Option<usize>, load_count: usize,
+      flags: u8, sample_duration: u8) -> TraceEntry {
+    TraceEntry {
+      id,
+      layout: TraceLayout::Chained,
+      next_entry_id,
+      load_count,
+      flags,
+      sample_duration
+    }
+  }
+
+  /// Constructs an entry of the `TraceLayout::Flat` layout.
+  pub fn flat(id: usize, load_count: usize, flags: u8, sample_duration: u8)
+      -> TraceEntry {
+    TraceEntry {
+      id,
+      layout: TraceLayout::Flat,
+      next_entry_id: None,
+      load_count,
+      flags,
+      sample_duration
+    }
+  }
+
   /// Returns the ID of the next entry if exists.
+  ///
+  /// This is always `None` for the `TraceLayout::Flat` layout.
   pub fn next_entry_id(&self) -> Option<usize> {
     self.next_entry_id
   }
@@ -39,35 +86,111 @@ impl TraceEntry {
     self.id
   }
 
-  /// Returns how many bytes are loaded.
+  /// Returns the layout the entry was read from.
+  pub fn layout(&self) -> TraceLayout {
+    self.layout
+  }
+
+  /// Returns how many blocks are loaded.
   pub fn load_count(&self) -> usize {
     self.load_count
   }
+
+  /// Returns the flags of the entry.
+  ///
+  /// On Windows 10, `TRACE_FLAG_EXECUTABLE` and `TRACE_FLAG_RESOURCE` tell
+  /// how the blocks were mapped.
+  pub fn flags(&self) -> u8 {
+    self.flags
+  }
+
+  /// Returns the sample duration stored alongside the flags.
+  pub fn sample_duration(&self) -> u8 {
+    self.sample_duration
+  }
 }
 
-pub(crate) trait TraceParser {
+/// The blocks were loaded as executable code.
+pub const TRACE_FLAG_EXECUTABLE: u8 = 0x02;
 
-  fn parse_trace(&self, content: &[u8])
-      -> super::Result<std::vec::Vec<TraceEntry>> {
-    let offset = usize::from_slice(&content[0x5c .. 0x60]);
-    let n = usize::from_slice(&content[0x60 .. 0x64]);
-    let mut entries = std::vec::Vec::<TraceEntry>::with_capacity(n);
-    let entry_size = 12usize;
-    let section = &content[offset .. offset + n * entry_size];
-
-    for i in 0 .. n {
-      let entry = &section[entry_size * i .. entry_size * (i + 1)];
-      entries.push(TraceEntry {
-        id: i,
+/// The blocks were loaded as a resource.
+pub const TRACE_FLAG_RESOURCE: u8 = 0x04;
+
+impl TraceLayout {
+
+  pub(crate) fn entry_size(self) -> usize {
+    match self {
+      TraceLayout::Chained => 12,
+      // Windows 10 entries are not chained anymore, they are 8 bytes long
+      TraceLayout::Flat => 8
+    }
+  }
+
+  pub(crate) fn parse_entry(self, id: usize, entry: &[u8]) -> TraceEntry {
+    match self {
+      TraceLayout::Chained => TraceEntry {
+        id,
+        layout: self,
         next_entry_id: match usize::from_slice(&entry[0 .. 0x4]) {
           0xFFFFFFFF => None,
           a => Some(a)
         },
-        load_count: usize::from_slice(&entry[0x4 .. 0x8])
-      });
+        load_count: usize::from_slice(&entry[0x4 .. 0x8]),
+        flags: entry[0x8],
+        sample_duration: entry[0x9]
+      },
+      TraceLayout::Flat => TraceEntry {
+        id,
+        layout: self,
+        next_entry_id: None,
+        load_count: usize::from_slice(&entry[0 .. 0x4]),
+        flags: entry[0x4],
+        sample_duration: entry[0x5]
+      }
+    }
+  }
+
+  pub(crate) fn write_entry(self, entry: &TraceEntry, out: &mut [u8]) {
+    use super::util::write;
+
+    match self {
+      TraceLayout::Chained => {
+        write(out, 0x0, 4, entry.next_entry_id.map_or(0xFFFFFFFF, |n| n as u64));
+        write(out, 0x4, 4, entry.load_count as u64);
+        out[0x8] = entry.flags;
+        out[0x9] = entry.sample_duration;
+      },
+      TraceLayout::Flat => {
+        write(out, 0x0, 4, entry.load_count as u64);
+        out[0x4] = entry.flags;
+        out[0x5] = entry.sample_duration;
+      }
     }
+  }
 
-    Ok(entries)
+  /// Returns the trace chain array of `content`.
+  pub(crate) fn section(self, content: &[u8]) -> super::Result<&[u8]> {
+    let offset: usize = read(content, Section::FileInformation, 0x5c, 4)?;
+    let n: usize = read(content, Section::FileInformation, 0x60, 4)?;
+    super::util::entries(content, Section::TraceChain, offset, n,
+      self.entry_size())
+  }
+}
+
+pub(crate) trait TraceParser {
+
+  fn trace_layout(&self) -> TraceLayout {
+    TraceLayout::Chained
+  }
+
+  fn trace_entry_size(&self) -> usize {
+    self.trace_layout().entry_size()
+  }
+
+  fn parse_trace(&self, content: &[u8])
+      -> super::Result<std::vec::Vec<TraceEntry>> {
+    Ok(super::iterator::TraceRefIterator::new(self.trace_layout(), content)?
+      .collect())
   }
 }
 
@@ -77,9 +200,16 @@ impl TraceParser for super::parser::Windows8{}
 
 impl TraceParser for super::parser::Windows10 {
 
-  fn parse_trace(&self, _content: &[u8])
-      -> super::Result<std::vec::Vec<TraceEntry>> {
-    Err(super::error::Error::NotImplemented)
+  fn trace_layout(&self) -> TraceLayout {
+    TraceLayout::Flat
+  }
+
+}
+
+impl TraceParser for super::parser::Windows11 {
+
+  fn trace_layout(&self) -> TraceLayout {
+    super::parser::Windows10.trace_layout()
   }
 
 }
@@ -0,0 +1,249 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+use std;
+
+/// A UTF-16LE string read from a Prefetch file.
+///
+/// Names and paths are stored by Windows as UTF-16 code units, which are not
+/// always valid UTF-16 (an unpaired surrogate for instance). The raw units are
+/// kept, along with a lossy decoding where invalid units are replaced by
+/// `U+FFFD`.
+///
+/// # Example
+///
+/// ```
+/// use libprefetch::Prefetch;
+///
+/// let prefetch =
+///     Prefetch::from_path("assets/WUAUCLT.EXE-399A8E72.pf").unwrap();
+///
+/// let name = prefetch.name_utf16();
+/// assert_eq!("WUAUCLT.EXE", name.as_str());
+/// assert_eq!("WUAUCLT.EXE", name.to_string_strict().unwrap());
+/// assert_eq!(0x57, name.units()[0]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Utf16String {
+  units: std::vec::Vec<u16>,
+  lossy: std::string::String
+}
+
+impl Utf16String {
+
+  /// Constructs a string from its UTF-16 code units, which may be invalid.
+  pub fn new(units: std::vec::Vec<u16>) -> Utf16String {
+    let lossy = std::char::decode_utf16(units.iter().cloned())
+      .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+      .collect();
+    Utf16String {
+      units,
+      lossy
+    }
+  }
+
+  /// Reads the units of `content` until the first NUL unit.
+  pub(crate) fn from_bytes(content: &[u8]) -> Utf16String {
+    Utf16Str::from_bytes(content).to_utf16_string()
+  }
+
+  /// Returns the raw UTF-16 code units, without the NUL terminator.
+  pub fn units(&self) -> &[u16] {
+    &self.units
+  }
+
+  /// Returns the lossy decoding of the string.
+  pub fn as_str(&self) -> &str {
+    &self.lossy
+  }
+
+  /// Returns the UTF-16LE bytes of the string, without NUL terminator.
+  pub(crate) fn to_bytes(&self) -> std::vec::Vec<u8> {
+    self.units.iter().flat_map(|u| u.to_le_bytes()).collect()
+  }
+
+  /// Returns whether the units are valid UTF-16.
+  pub fn is_valid(&self) -> bool {
+    std::char::decode_utf16(self.units.iter().cloned()).all(|c| c.is_ok())
+  }
+
+  /// Decodes the string, failing with `Error::InvalidUtf16` on the first
+  /// unpaired surrogate.
+  pub fn to_string_strict(&self) -> super::Result<std::string::String> {
+    let mut s = std::string::String::with_capacity(self.lossy.len());
+    let mut index = 0;
+    for c in std::char::decode_utf16(self.units.iter().cloned()) {
+      match c {
+        Ok(c) => {
+          s.push(c);
+          index += c.len_utf16();
+        },
+        Err(_) => return Err(super::error::Error::InvalidUtf16(index))
+      }
+    }
+    Ok(s)
+  }
+}
+
+impl<'a> From<&'a str> for Utf16String {
+
+  fn from(s: &'a str) -> Utf16String {
+    Utf16String::new(s.encode_utf16().collect())
+  }
+}
+
+impl std::fmt::Display for Utf16String {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    f.write_str(&self.lossy)
+  }
+}
+
+/// Iterator through the UTF-16 code units of a `Utf16Str`.
+pub type Utf16Units<'a> =
+  std::iter::Map<std::slice::ChunksExact<'a, u8>, fn(&[u8]) -> u16>;
+
+fn unit(bytes: &[u8]) -> u16 {
+  bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+/// A UTF-16LE string borrowed from the data of a Prefetch file.
+///
+/// This is the borrowed counterpart of `Utf16String`, returned by
+/// `PrefetchRef`: nothing is decoded until asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Utf16Str<'a> {
+  bytes: &'a [u8]
+}
+
+impl<'a> Utf16Str<'a> {
+
+  /// Reads the units of `content` until the first NUL unit.
+  pub(crate) fn from_bytes(content: &'a [u8]) -> Utf16Str<'a> {
+    let length = content.chunks_exact(2)
+      .position(|c| unit(c) == 0)
+      .unwrap_or(content.len() / 2);
+    Utf16Str {
+      bytes: &content[.. length * 2]
+    }
+  }
+
+  /// Returns the raw UTF-16LE bytes, without the NUL terminator.
+  pub fn as_bytes(&self) -> &'a [u8] {
+    self.bytes
+  }
+
+  /// Returns an iterator through the raw UTF-16 code units.
+  pub fn units(&self) -> Utf16Units<'a> {
+    self.bytes.chunks_exact(2).map(unit as fn(&[u8]) -> u16)
+  }
+
+  /// Returns the number of UTF-16 code units.
+  pub fn len(&self) -> usize {
+    self.bytes.len() / 2
+  }
+
+  /// Returns whether the string is empty.
+  pub fn is_empty(&self) -> bool {
+    self.bytes.is_empty()
+  }
+
+  /// Returns the lossy decoding of the string.
+  pub fn to_string_lossy(&self) -> std::string::String {
+    std::char::decode_utf16(self.units())
+      .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+      .collect()
+  }
+
+  /// Decodes the string, failing with `Error::InvalidUtf16` on the first
+  /// unpaired surrogate.
+  pub fn to_string_strict(&self) -> super::Result<std::string::String> {
+    let mut s = std::string::String::with_capacity(self.len());
+    let mut index = 0;
+    for c in std::char::decode_utf16(self.units()) {
+      match c {
+        Ok(c) => {
+          s.push(c);
+          index += c.len_utf16();
+        },
+        Err(_) => return Err(super::error::Error::InvalidUtf16(index))
+      }
+    }
+    Ok(s)
+  }
+
+  /// Copies the string into an owned `Utf16String`.
+  pub fn to_utf16_string(&self) -> Utf16String {
+    Utf16String::new(self.units().collect())
+  }
+}
+
+impl<'a> std::fmt::Display for Utf16Str<'a> {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    for c in std::char::decode_utf16(self.units()) {
+      std::fmt::Write::write_char(f,
+        c.unwrap_or(std::char::REPLACEMENT_CHARACTER))?;
+    }
+    Ok(())
+  }
+}
+
+// Strings are serialized as an enumeration, so that formats which do not
+// describe their data can read them back: valid strings as a `Str`, the others
+// as the `Units` they are made of, so that nothing is lost.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+#[serde(rename = "Utf16String")]
+enum SerializedUtf16String<'a> {
+  Str(&'a str),
+  Units(&'a [u16])
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "Utf16String")]
+enum DeserializedUtf16String {
+  Str(std::string::String),
+  Units(std::vec::Vec<u16>)
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Utf16String {
+
+  fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where S: serde::Serializer {
+    match self.is_valid() {
+      true => SerializedUtf16String::Str(&self.lossy),
+      false => SerializedUtf16String::Units(&self.units)
+    }.serialize(serializer)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Utf16String {
+
+  fn deserialize<D>(deserializer: D) -> std::result::Result<Utf16String, D::Error>
+    where D: serde::Deserializer<'de> {
+    Ok(match DeserializedUtf16String::deserialize(deserializer)? {
+      DeserializedUtf16String::Str(s) => Utf16String::new(s.encode_utf16().collect()),
+      DeserializedUtf16String::Units(units) => Utf16String::new(units)
+    })
+  }
+}
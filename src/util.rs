@@ -15,7 +15,6 @@
 //
 // Authors: zadig <thomas chr(0x40) bailleux.me>
 //          jasa <jan.starke (0x40) t-systems.com>
-use std;
 
 pub(crate) trait FromSlice<T> {
   fn from_slice(buf: &[T]) -> Self;
@@ -25,10 +24,18 @@ pub(crate) trait FromSlice<T> {
 impl FromSlice<u8> for usize {
   fn from_slice(buf: &[u8]) -> Self {
     let mut result = 0usize;
-    let mut p = 0u32;
-    for i in 0..buf.len() {
-      result += (buf[i] as usize) * 256usize.pow(p);
-      p += 1;
+    for (p, b) in buf.iter().enumerate() {
+      result += (*b as usize) << (8 * p);
+    }
+    result
+  }
+}
+
+impl FromSlice<u8> for u16 {
+  fn from_slice(buf: &[u8]) -> Self {
+    let mut result = 0u16;
+    for (p, b) in buf.iter().enumerate() {
+      result += (*b as u16) << (8 * p);
     }
     result
   }
@@ -37,10 +44,8 @@ impl FromSlice<u8> for usize {
 impl FromSlice<u8> for u32 {
   fn from_slice(buf: &[u8]) -> Self {
     let mut result = 0u32;
-    let mut p = 0u32;
-    for i in 0..buf.len() {
-      result += (buf[i] as u32) * 256u32.pow(p);
-      p += 1;
+    for (p, b) in buf.iter().enumerate() {
+      result += (*b as u32) << (8 * p);
     }
     result
   }
@@ -49,10 +54,8 @@ impl FromSlice<u8> for u32 {
 impl FromSlice<u8> for i32 {
   fn from_slice(buf: &[u8]) -> Self {
     let mut result = 0i32;
-    let mut p = 0u32;
-    for i in 0..buf.len() {
-      result += (buf[i] as i32) * 256i32.pow(p);
-      p += 1;
+    for (p, b) in buf.iter().enumerate() {
+      result += (*b as i32) << (8 * p);
     }
     result
   }
@@ -61,26 +64,57 @@ impl FromSlice<u8> for i32 {
 impl FromSlice<u8> for u64 {
   fn from_slice(buf: &[u8]) -> Self {
     let mut result = 0u64;
-    let mut p = 0u32;
-    for i in 0..buf.len() {
-      result += (buf[i] as u64) * 256u64.pow(p);
-      p += 1;
+    for (p, b) in buf.iter().enumerate() {
+      result += (*b as u64) << (8 * p);
     }
     result
   }
 }
 
-pub(crate) fn fetch_unicode_string(content: &[u8], offset: usize, max_length:
-  usize) -> std::string::String {
-  let mut s = std::string::String::new();
-  let mut i = 0;
-  let limit = content.len();
-  let limit2 = max_length * 2 + 2;
-  while i < limit2 && i < limit && content[offset + i] != 0 {
-    s.push(content[offset + i] as char);
-    i += 2;
+/// Writes `value` as a little endian integer of `length` bytes at `offset`.
+pub(crate) fn write(content: &mut [u8], offset: usize, length: usize,
+  value: u64) {
+  for (p, b) in content[offset .. offset + length].iter_mut().enumerate() {
+    *b = (value >> (8 * p)) as u8;
   }
+}
+
+/// Returns `length` bytes at `offset` of a section, or an
+/// `Error::OutOfBounds` naming the section.
+pub(crate) fn slice(content: &[u8], section: super::error::Section,
+  offset: usize, length: usize) -> super::Result<&[u8]> {
+  offset.checked_add(length)
+    .and_then(|end| content.get(offset .. end))
+    .ok_or(super::error::Error::OutOfBounds(section, offset, length))
+}
+
+/// Reads a little endian integer of `length` bytes at `offset` of a section.
+pub(crate) fn read<T: FromSlice<u8>>(content: &[u8],
+  section: super::error::Section, offset: usize, length: usize)
+    -> super::Result<T> {
+  slice(content, section, offset, length).map(T::from_slice)
+}
+
+/// Returns the `n` entries of `entry_size` bytes at `offset` of a section.
+pub(crate) fn entries(content: &[u8], section: super::error::Section,
+  offset: usize, n: usize, entry_size: usize) -> super::Result<&[u8]> {
+  let length = n.checked_mul(entry_size)
+    .ok_or(super::error::Error::EntryOutOfBounds(section, 0, n))?;
+  slice(content, section, offset, length)
+}
+
+/// Reads a string of at most `max_length` UTF-16 units at `offset`,
+/// stopping at the first NUL character.
+pub(crate) fn fetch_unicode_string(content: &[u8],
+  section: super::error::Section, offset: usize, max_length: usize)
+    -> super::Result<super::utf16::Utf16Str<'_>> {
+  let length = max_length.checked_mul(2)
+    .ok_or(super::error::Error::OutOfBounds(section, offset, max_length))?;
+  let units = slice(content, section, offset, length)?;
 
-  s
+  Ok(super::utf16::Utf16Str::from_bytes(units))
 }
 
+/// Fails to compile if `T` cannot be shared between threads, see the checks
+/// of `Prefetch` and `PrefetchRef`.
+pub(crate) fn assert_send_sync<T: Send + Sync>() {}
@@ -17,7 +17,8 @@
 //          jasa <jan.starke (0x40) t-systems.com>
 
 use std;
-use super::util::FromSlice;
+use super::error::Section;
+use super::util::{FromSlice, read};
 
 /// A volume.
 ///
@@ -28,17 +29,169 @@ use super::util::FromSlice;
 /// you'll see here a new entry with details about your USB stick.
 ///
 /// If all files are on C:, you will have only one single entry.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VolumeEntry {
   id: usize,
-  device_path: std::string::String,
-  creation_time: u64,
+  device_path: super::utf16::Utf16String,
+  creation_time: super::FileTime,
   serial_number: u32,
-  pub(crate) directories: std::vec::Vec<std::string::String>
+  pub(crate) directories: std::vec::Vec<super::utf16::Utf16String>,
+  pub(crate) file_references: Option<std::vec::Vec<FileReference>>
+}
+
+/// A NTFS file reference.
+///
+/// It locates a file in the `$MFT` of the volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileReference {
+  mft_entry_index: u64,
+  sequence_number: u16
+}
+
+impl FileReference {
+
+  /// Constructs a file reference.
+  pub fn new(mft_entry_index: u64, sequence_number: u16) -> FileReference {
+    FileReference {
+      mft_entry_index,
+      sequence_number
+    }
+  }
+
+  /// Returns the NTFS MFT entry index.
+  pub fn mft_entry_index(&self) -> u64 {
+    self.mft_entry_index
+  }
+
+  /// Returns the NTFS sequence number.
+  pub fn sequence_number(&self) -> u16 {
+    self.sequence_number
+  }
+}
+
+/// The identifier of a volume in the `\VOLUME{creation time-serial number}`
+/// device paths of Windows 10, such as `\VOLUME{01d7b2a4c1e3f500-7e3f9a21}`.
+///
+/// Both parts are hexadecimal: the creation time is a FILETIME of 16 digits,
+/// the serial number has 8 digits.
+///
+/// # Example
+///
+/// ```
+/// use libprefetch::volume::VolumeId;
+///
+/// let path = r"\VOLUME{01d7b2a4c1e3f500-7e3f9a21}\WINDOWS\SYSTEM32\CMD.EXE";
+/// let (id, rest) = VolumeId::parse(path).unwrap();
+/// assert_eq!(0x7e3f9a21, id.serial_number());
+/// assert_eq!(0x01d7b2a4c1e3f500, id.creation_time().value());
+/// assert_eq!(r"\WINDOWS\SYSTEM32\CMD.EXE", rest);
+/// assert_eq!(r"\VOLUME{01d7b2a4c1e3f500-7e3f9a21}", id.to_string());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VolumeId {
+  creation_time: super::FileTime,
+  serial_number: u32
+}
+
+impl VolumeId {
+
+  /// Constructs an identifier.
+  pub fn new(creation_time: super::FileTime, serial_number: u32) -> VolumeId {
+    VolumeId {
+      creation_time,
+      serial_number
+    }
+  }
+
+  /// Parses the identifier at the start of `path`, compared without case, and
+  /// returns it with the rest of the path. `None` means that `path` does not
+  /// start with a `\VOLUME{...}` device.
+  pub fn parse(path: &str) -> Option<(VolumeId, &str)> {
+    const PREFIX: &str = r"\VOLUME{";
+    if !path.get(.. PREFIX.len())?.eq_ignore_ascii_case(PREFIX) {
+      return None;
+    }
+    let path = &path[PREFIX.len() ..];
+    let end = path.find('}')?;
+    let (time, serial) = path[.. end].split_once('-')?;
+    let rest = &path[end + 1 ..];
+    let hex = |s: &str, digits| s.len() == digits
+      && s.bytes().all(|b| b.is_ascii_hexdigit());
+    if !hex(time, 16) || !hex(serial, 8)
+        || !(rest.is_empty() || rest.starts_with('\\')) {
+      return None;
+    }
+    Some((VolumeId {
+      creation_time: super::FileTime::new(u64::from_str_radix(time, 16).ok()?),
+      serial_number: u32::from_str_radix(serial, 16).ok()?
+    }, rest))
+  }
+
+  /// Returns the creation time of the volume.
+  pub fn creation_time(&self) -> super::FileTime {
+    self.creation_time
+  }
+
+  /// Returns the serial number of the volume.
+  pub fn serial_number(&self) -> u32 {
+    self.serial_number
+  }
+}
+
+impl std::fmt::Display for VolumeId {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, r"\VOLUME{{{:016x}-{:08x}}}", self.creation_time.value(),
+      self.serial_number)
+  }
+}
+
+/// Splits `path` after the device path `device`, compared without case.
+fn strip_device<'p>(path: &'p str, device: &str) -> Option<&'p str> {
+  let device = device.trim_end_matches('\\');
+  match path.get(.. device.len()) {
+    Some(head) if head.eq_ignore_ascii_case(device) => {
+      let rest = &path[device.len() ..];
+      match rest.is_empty() || rest.starts_with('\\') {
+        true => Some(rest),
+        false => None
+      }
+    },
+    _ => None
+  }
 }
 
 impl VolumeEntry {
 
+  /// Constructs a volume without directories nor file references.
+  pub fn new(id: usize, device_path: super::utf16::Utf16String,
+      creation_time: super::FileTime, serial_number: u32) -> VolumeEntry {
+    VolumeEntry {
+      id,
+      device_path,
+      creation_time,
+      serial_number,
+      directories: std::vec::Vec::new(),
+      file_references: None
+    }
+  }
+
+  /// Sets the directories used on the volume.
+  pub fn with_directories(mut self,
+      directories: std::vec::Vec<super::utf16::Utf16String>) -> VolumeEntry {
+    self.directories = directories;
+    self
+  }
+
+  /// Sets the NTFS file references, stored since Windows Vista.
+  pub fn with_file_references(mut self,
+      file_references: std::vec::Vec<FileReference>) -> VolumeEntry {
+    self.file_references = Some(file_references);
+    self
+  }
+
   /// Returns the ID of the entry.
   pub fn id(&self) -> usize {
     self.id
@@ -46,11 +199,16 @@ impl VolumeEntry {
 
   /// Returns the path to the device.
   pub fn device_path(&self) -> &str {
+    self.device_path.as_str()
+  }
+
+  /// Returns the path to the device, as stored in the file.
+  pub fn device_path_utf16(&self) -> &super::utf16::Utf16String {
     &self.device_path
   }
 
   /// Returns the creation time.
-  pub fn creation_time(&self) -> u64 {
+  pub fn creation_time(&self) -> super::FileTime {
     self.creation_time
   }
 
@@ -59,96 +217,337 @@ impl VolumeEntry {
     self.serial_number
   }
 
+  /// Returns the identifier built from the creation time and the serial
+  /// number of the entry.
+  pub fn volume_id(&self) -> VolumeId {
+    VolumeId::new(self.creation_time, self.serial_number)
+  }
+
+  /// Returns the identifier parsed from the device path, if it has the
+  /// `\VOLUME{...}` form.
+  pub fn parsed_volume_id(&self) -> Option<VolumeId> {
+    VolumeId::parse(self.device_path.as_str()).map(|(id, _)| id)
+  }
+
+  /// Checks the identifier of the device path against the creation time and
+  /// the serial number of the entry. `None` means that the device path does
+  /// not have the `\VOLUME{...}` form.
+  pub fn volume_id_matches(&self) -> Option<bool> {
+    self.parsed_volume_id().map(|id| id == self.volume_id())
+  }
+
+  /// Rewrites `path`, which starts with the device path of the entry or with
+  /// its `\VOLUME{...}` identifier, to start with the identifier. `None`
+  /// means that `path` is on another volume.
+  ///
+  /// The path is upper-cased like NTFS does, one character at a time, and
+  /// trailing backslashes are removed, so that paths of both forms can be
+  /// compared.
+  pub fn normalize_path(&self, path: &str) -> Option<std::string::String> {
+    let id = self.volume_id();
+    let rest = strip_device(path, self.device_path.as_str())
+      .or_else(|| match VolumeId::parse(path) {
+        Some((parsed, rest)) if parsed == id => Some(rest),
+        _ => None
+      })?;
+    let units: std::vec::Vec<u16> = rest.trim_end_matches('\\')
+      .encode_utf16().collect();
+    Some(format!("{}{}", id,
+      std::string::String::from_utf16_lossy(&super::hash::upper_case(&units))))
+  }
+
   /// Returns an iterator through all directories used on the volume.
   pub fn directories(&self)
-      -> super::Result<super::iterator::DirectoryIterator> {
+      -> super::Result<super::iterator::DirectoryIterator<'_>> {
     super::iterator::DirectoryIterator::new(self)
   }
 
+  /// Returns all directories used on the volume, as stored in the file.
+  pub fn directories_utf16(&self) -> &[super::utf16::Utf16String] {
+    &self.directories
+  }
+
+  /// Returns an iterator through the NTFS file references of the volume.
+  ///
+  /// File references are only stored since Windows Vista.
+  pub fn file_references(&self)
+      -> super::Result<super::iterator::FileReferenceIterator<'_>> {
+    super::iterator::FileReferenceIterator::new(self)
+  }
+
 }
 
-fn fetch_directories(content: &[u8], offset: usize, n: usize)
-    -> std::vec::Vec<std::string::String> {
-  let mut directories = std::vec::Vec::<std::string::String>::with_capacity(n);
+/// A volume, borrowed from the data of a `PrefetchRef`.
+///
+/// This is the borrowed counterpart of `VolumeEntry`: directories and file
+/// references are only read when iterated.
+#[derive(Debug, Clone, Copy)]
+pub struct VolumeEntryRef<'a> {
+  id: usize,
+  section: &'a [u8],
+  device_path: super::utf16::Utf16Str<'a>,
+  creation_time: super::FileTime,
+  serial_number: u32,
+  directories_offset: usize,
+  directories_count: usize,
+  file_references: Option<(usize, usize)>
+}
+
+impl<'a> VolumeEntryRef<'a> {
+
+  /// Returns the ID of the entry.
+  pub fn id(&self) -> usize {
+    self.id
+  }
+
+  /// Returns the path to the device.
+  pub fn device_path(&self) -> super::utf16::Utf16Str<'a> {
+    self.device_path
+  }
+
+  /// Returns the creation time.
+  pub fn creation_time(&self) -> super::FileTime {
+    self.creation_time
+  }
+
+  /// Returns the serial number of the volume.
+  pub fn serial_number(&self) -> u32 {
+    self.serial_number
+  }
+
+  /// Returns the identifier built from the creation time and the serial
+  /// number of the entry.
+  pub fn volume_id(&self) -> VolumeId {
+    VolumeId::new(self.creation_time, self.serial_number)
+  }
+
+  /// Returns the identifier parsed from the device path, if it has the
+  /// `\VOLUME{...}` form.
+  pub fn parsed_volume_id(&self) -> Option<VolumeId> {
+    VolumeId::parse(&self.device_path.to_string_lossy()).map(|(id, _)| id)
+  }
 
-  let section_offset = usize::from_slice(&content[0x6c .. 0x70]);
-  let section = &content[section_offset .. ];
+  /// Checks the identifier of the device path against the creation time and
+  /// the serial number of the entry. `None` means that the device path does
+  /// not have the `\VOLUME{...}` form.
+  pub fn volume_id_matches(&self) -> Option<bool> {
+    self.parsed_volume_id().map(|id| id == self.volume_id())
+  }
+
+  /// Returns an iterator through all directories used on the volume.
+  pub fn directories(&self) -> super::iterator::DirectoryRefIterator<'a> {
+    super::iterator::DirectoryRefIterator::new(self.section,
+      self.directories_offset, self.directories_count)
+  }
 
-  let mut start_offset = offset;
-  for _ in 0 .. n {
-    let str_size = usize::from_slice(
-        &section[start_offset .. start_offset + 2]);
-    let start = start_offset + 2;
-    let end = start + str_size * 2 + 2;
-    let mut i = 0;
-    let mut s = std::string::String::with_capacity(str_size);
-    while i < (str_size + 1) * 2 && section[start + i] != 0 {
-      s.push(section[start + i] as char);
-      i += 2;
+  /// Returns an iterator through the NTFS file references of the volume.
+  ///
+  /// File references are only stored since Windows Vista.
+  pub fn file_references(&self)
+      -> super::Result<super::iterator::FileReferenceRefIterator<'a>> {
+    match self.file_references {
+      Some((offset, size)) => Ok(super::iterator::FileReferenceRefIterator::new(
+        fetch_file_references(self.section, offset, size)?)),
+      None => Err(super::error::Error::NotImplemented)
     }
-    directories.push(s);
-    start_offset = end;
   }
-  directories
 
+  /// Copies the entry into an owned `VolumeEntry`.
+  pub fn to_entry(&self) -> super::Result<VolumeEntry> {
+    Ok(VolumeEntry {
+      id: self.id,
+      device_path: self.device_path.to_utf16_string(),
+      creation_time: self.creation_time,
+      serial_number: self.serial_number,
+      directories: self.directories()
+        .map(|d| d.map(|d| d.to_utf16_string()))
+        .collect::<super::Result<_>>()?,
+      file_references: match self.file_references {
+        Some(_) => Some(self.file_references()?.collect()),
+        None => None
+      }
+    })
+  }
+}
+
+/// Returns the array of NTFS file references at `offset` of a volume section.
+fn fetch_file_references(section: &[u8], offset: usize, size: usize)
+    -> super::Result<&[u8]> {
+  // The array is preceded by a 16 bytes header: a version, the number of
+  // references, and 8 unknown bytes.
+  let header_size = 16usize;
+  if size < header_size {
+    return Ok(&[]);
+  }
+  // The offset comes from the file, the additions must not overflow.
+  let at = |delta: usize| offset.checked_add(delta)
+    .ok_or(super::error::Error::OutOfBounds(Section::FileReferences, offset,
+      delta));
+  let n = std::cmp::min(
+    read::<usize>(section, Section::FileReferences, at(0x4)?, 4)?,
+    (size - header_size) / 8);
+  super::util::entries(section, Section::FileReferences, at(header_size)?, n,
+    8)
+}
+
+pub(crate) fn parse_file_reference(r: &[u8]) -> FileReference {
+  FileReference {
+    mft_entry_index: u64::from_slice(&r[0x0 .. 0x6]),
+    sequence_number: u16::from_slice(&r[0x6 .. 0x8])
+  }
+}
+
+/// Size of a volume entry, and whether it stores NTFS file references.
+pub(crate) struct VolumeLayout {
+  pub(crate) entry_size: usize,
+  file_references: bool
 }
 
-fn generic(content: &[u8], entry_size: usize)
-    -> std::vec::Vec<VolumeEntry> {
+const WINDOWS_XP_2003: VolumeLayout = VolumeLayout {
+  entry_size: 40,
+  file_references: false
+};
+
+const WINDOWS_VISTA: VolumeLayout = VolumeLayout {
+  entry_size: 104,
+  file_references: true
+};
+
+const WINDOWS_10: VolumeLayout = VolumeLayout {
+  entry_size: 96,
+  file_references: true
+};
+
+impl VolumeLayout {
 
-  let offset = usize::from_slice(&content[0x6c .. 0x70]);
-  let n = usize::from_slice(&content[0x70 .. 0x74]);
-  let section = &content[offset .. ];
-  let mut entries = std::vec::Vec::<VolumeEntry>::with_capacity(n);
-  for i in 0 .. n {
-    let entry = &section[i * entry_size .. (i + 1) * entry_size];
-    entries.push(VolumeEntry {
-      id: i,
-      device_path: super::util::fetch_unicode_string(section,
+  pub(crate) fn parse_entry<'a>(&self, id: usize, entry: &[u8],
+      section: &'a [u8]) -> super::Result<VolumeEntryRef<'a>> {
+    Ok(VolumeEntryRef {
+      id,
+      section,
+      device_path: super::util::fetch_unicode_string(section, Section::Volumes,
         usize::from_slice(&entry[0x0 .. 0x4]),
         usize::from_slice(&entry[0x4 .. 0x8])
-      ),
-      creation_time: u64::from_slice(&entry[0x8 .. 0x10]),
+      )?,
+      creation_time: super::FileTime::new(u64::from_slice(&entry[0x8 .. 0x10])),
       serial_number: u32::from_slice(&entry[0x10 .. 0x14]),
-      directories: fetch_directories(content,
-        usize::from_slice(&entry[0x1c .. 0x20]),
-        usize::from_slice(&entry[0x20 .. 0x24])),
-    });
+      directories_offset: usize::from_slice(&entry[0x1c .. 0x20]),
+      directories_count: usize::from_slice(&entry[0x20 .. 0x24]),
+      file_references: if self.file_references {
+        Some((usize::from_slice(&entry[0x14 .. 0x18]),
+          usize::from_slice(&entry[0x18 .. 0x1c])))
+      } else {
+        None
+      }
+    })
+  }
+
+  /// Writes the volumes section: the entries array, followed by the device
+  /// path, the file references and the directories of each volume.
+  pub(crate) fn write_section(&self, volumes: &[VolumeEntry])
+      -> std::vec::Vec<u8> {
+    use super::util::write;
+
+    let mut section = vec![0u8; volumes.len() * self.entry_size];
+    for (i, volume) in volumes.iter().enumerate() {
+      let entry = i * self.entry_size;
+
+      let offset = section.len() as u64;
+      write(&mut section, entry, 4, offset);
+      write(&mut section, entry + 0x4, 4,
+        volume.device_path.units().len() as u64);
+      section.extend(volume.device_path.to_bytes());
+      section.extend(&[0, 0]);
+      let aligned = (section.len() + 7) & !7;
+      section.resize(aligned, 0);
+
+      write(&mut section, entry + 0x8, 8, volume.creation_time.value());
+      write(&mut section, entry + 0x10, 4, volume.serial_number as u64);
+
+      if self.file_references {
+        let references = volume.file_references.as_ref()
+          .map_or(&[][..], |r| &r[..]);
+        let offset = section.len() as u64;
+        write(&mut section, entry + 0x14, 4, offset);
+        write(&mut section, entry + 0x18, 4, 16 + references.len() as u64 * 8);
+        // Version, number of references and 8 unknown bytes
+        let start = section.len();
+        section.resize(start + 16, 0);
+        write(&mut section, start, 4, 3);
+        write(&mut section, start + 0x4, 4, references.len() as u64);
+        for r in references {
+          let start = section.len();
+          section.resize(start + 8, 0);
+          write(&mut section, start, 6, r.mft_entry_index);
+          write(&mut section, start + 6, 2, r.sequence_number as u64);
+        }
+      }
+
+      let offset = section.len() as u64;
+      write(&mut section, entry + 0x1c, 4, offset);
+      write(&mut section, entry + 0x20, 4, volume.directories.len() as u64);
+      for directory in &volume.directories {
+        section.extend(&(directory.units().len() as u16).to_le_bytes());
+        section.extend(directory.to_bytes());
+        section.extend(&[0, 0]);
+      }
+    }
+
+    section
   }
 
-  entries
+  /// Returns the volumes section of `content`, and the entries array at its
+  /// start.
+  pub(crate) fn sections<'a>(&self, content: &'a [u8])
+      -> super::Result<(&'a [u8], &'a [u8])> {
+    let offset: usize = read(content, Section::FileInformation, 0x6c, 4)?;
+    let n: usize = read(content, Section::FileInformation, 0x70, 4)?;
+    let size: usize = read(content, Section::FileInformation, 0x74, 4)?;
+    let section = super::util::slice(content, Section::Volumes, offset, size)?;
+    let array = super::util::entries(section, Section::Volumes, 0, n,
+      self.entry_size)?;
+    Ok((section, array))
+  }
 }
 
 pub(crate) trait VolumeParser {
 
+  fn volume_layout(&self) -> &'static VolumeLayout;
+
   fn parse_volumes(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<VolumeEntry>>;
+    -> super::Result<std::vec::Vec<VolumeEntry>> {
+    super::iterator::VolumeRefIterator::new(self.volume_layout(), content)?
+      .map(|entry| entry.and_then(|e| e.to_entry()))
+      .collect()
+  }
 }
 
 impl VolumeParser for super::parser::WindowsXp2003 {
-  fn parse_volumes(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<VolumeEntry>> {
-    Ok(generic(content, 40))
+  fn volume_layout(&self) -> &'static VolumeLayout {
+    &WINDOWS_XP_2003
   }
 }
 
 impl VolumeParser for super::parser::WindowsVista7 {
-  fn parse_volumes(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<VolumeEntry>> {
-    Ok(generic(content, 104))
+  fn volume_layout(&self) -> &'static VolumeLayout {
+    &WINDOWS_VISTA
   }
 }
 
 impl VolumeParser for super::parser::Windows8 {
-  fn parse_volumes(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<VolumeEntry>> {
-    Ok(generic(content, 104))
+  fn volume_layout(&self) -> &'static VolumeLayout {
+    &WINDOWS_VISTA
   }
 }
 
 impl VolumeParser for super::parser::Windows10 {
-  fn parse_volumes(&self, content: &[u8])
-    -> super::Result<std::vec::Vec<VolumeEntry>> {
-    Ok(generic(content, 96))
+  fn volume_layout(&self) -> &'static VolumeLayout {
+    &WINDOWS_10
+  }
+}
+
+impl VolumeParser for super::parser::Windows11 {
+  fn volume_layout(&self) -> &'static VolumeLayout {
+    super::parser::Windows10.volume_layout()
   }
 }
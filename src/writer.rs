@@ -0,0 +1,215 @@
+// This file is part of libprefetch.
+//
+// libprefetch is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// libprefetch is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with libprefetch.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Authors: zadig <thomas chr(0x40) bailleux.me>
+//          jasa <jan.starke (0x40) t-systems.com>
+
+
+//! Writes Prefetch files from an in-memory model.
+
+use std;
+use super::FormatVersion;
+use super::util::write;
+
+/// A Prefetch file writer.
+///
+/// It writes the layout of any `FormatVersion`: the sections are stored in
+/// the order Windows uses, metrics, trace chain, filename strings and volumes.
+/// Fields which are not part of the model are zeroed. Parsing the written file
+/// gives back the model.
+///
+/// # Example
+///
+/// ```
+/// use libprefetch::{FileTime, FormatVersion, Prefetch};
+/// use libprefetch::metric::MetricEntry;
+/// use libprefetch::trace::TraceEntry;
+/// use libprefetch::volume::VolumeEntry;
+/// use libprefetch::writer::Writer;
+///
+/// let data = Writer::new(FormatVersion::Windows10, "CALC.EXE".into(), 0x77010bf2)
+///   .last_execution_times(vec![FileTime::new(0x01d4a3c2e8f1b200)])
+///   .execution_counter(1)
+///   .metrics(vec![MetricEntry::new(0,
+///     "\\DEVICE\\HARDDISKVOLUME2\\WINDOWS\\SYSTEM32\\CALC.EXE".into(), 0, 2)])
+///   .trace(vec![TraceEntry::flat(0, 4, 0x02, 1), TraceEntry::flat(1, 1, 0x04, 0)])
+///   .volumes(vec![VolumeEntry::new(0, "\\DEVICE\\HARDDISKVOLUME2".into(),
+///     FileTime::new(0x01d3c5f9a6b7c8d0), 0x4a3b2c1d)])
+///   .to_bytes();
+///
+/// let prefetch = Prefetch::new(&data[..]).unwrap();
+/// assert_eq!("CALC.EXE", prefetch.name());
+/// assert_eq!(2, prefetch.trace().unwrap().count());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Writer {
+  version: FormatVersion,
+  name: super::utf16::Utf16String,
+  hash: u32,
+  last_execution_times: std::vec::Vec<super::FileTime>,
+  execution_counter: usize,
+  metrics: std::vec::Vec<super::metric::MetricEntry>,
+  trace: std::vec::Vec<super::trace::TraceEntry>,
+  volumes: std::vec::Vec<super::volume::VolumeEntry>
+}
+
+impl Writer {
+
+  /// Constructs a writer of an empty file.
+  ///
+  /// The name is truncated to the 29 UTF-16 units the header can hold.
+  pub fn new(version: FormatVersion, name: super::utf16::Utf16String,
+      hash: u32) -> Writer {
+    Writer {
+      version,
+      name,
+      hash,
+      last_execution_times: std::vec::Vec::new(),
+      execution_counter: 0,
+      metrics: std::vec::Vec::new(),
+      trace: std::vec::Vec::new(),
+      volumes: std::vec::Vec::new()
+    }
+  }
+
+  /// Constructs a writer of the content of a parsed file, failing if one of
+  /// its sections could not be parsed.
+  pub fn from_prefetch(prefetch: &super::Prefetch) -> super::Result<Writer> {
+    Ok(Writer {
+      version: prefetch.version(),
+      name: prefetch.name_utf16().clone(),
+      hash: prefetch.hash(),
+      last_execution_times: prefetch.last_execution_times().to_vec(),
+      execution_counter: prefetch.execution_counter(),
+      metrics: prefetch.metrics()?.cloned().collect(),
+      trace: prefetch.trace()?.cloned().collect(),
+      volumes: prefetch.volumes()?.cloned().collect()
+    })
+  }
+
+  /// Sets the run times, the most recent first.
+  ///
+  /// Windows XP to 7 only store the first one, Windows 8 and later the first
+  /// eight.
+  pub fn last_execution_times(mut self,
+      times: std::vec::Vec<super::FileTime>) -> Writer {
+    self.last_execution_times = times;
+    self
+  }
+
+  /// Sets the run counter.
+  pub fn execution_counter(mut self, counter: usize) -> Writer {
+    self.execution_counter = counter;
+    self
+  }
+
+  /// Sets the file metrics.
+  pub fn metrics(mut self, metrics: std::vec::Vec<super::metric::MetricEntry>)
+      -> Writer {
+    self.metrics = metrics;
+    self
+  }
+
+  /// Sets the trace chain, written with the layout of the version.
+  pub fn trace(mut self, trace: std::vec::Vec<super::trace::TraceEntry>)
+      -> Writer {
+    self.trace = trace;
+    self
+  }
+
+  /// Sets the volumes.
+  pub fn volumes(mut self, volumes: std::vec::Vec<super::volume::VolumeEntry>)
+      -> Writer {
+    self.volumes = volumes;
+    self
+  }
+
+  /// Returns the content of the file.
+  pub fn to_bytes(&self) -> std::vec::Vec<u8> {
+    let parser = self.version.parser();
+    let metric_layout = parser.metric_layout();
+    let trace_layout = parser.trace_layout();
+    let volume_layout = parser.volume_layout();
+
+    let metrics_offset = self.version.file_information_end();
+    let trace_offset = metrics_offset
+      + self.metrics.len() * metric_layout.entry_size;
+    let names_offset = trace_offset
+      + self.trace.len() * trace_layout.entry_size();
+
+    let mut data = vec![0u8; names_offset];
+    for (i, metric) in self.metrics.iter().enumerate() {
+      let start = metrics_offset + i * metric_layout.entry_size;
+      metric_layout.write_entry(metric, data.len() - names_offset,
+        &mut data[start .. start + metric_layout.entry_size]);
+      data.extend(metric.filename_utf16().to_bytes());
+      data.extend(&[0, 0]);
+    }
+    for (i, trace) in self.trace.iter().enumerate() {
+      let start = trace_offset + i * trace_layout.entry_size();
+      trace_layout.write_entry(trace,
+        &mut data[start .. start + trace_layout.entry_size()]);
+    }
+    let names_length = data.len() - names_offset;
+    let aligned = (data.len() + 7) & !7;
+    data.resize(aligned, 0);
+    let volumes_offset = data.len();
+    let volumes = volume_layout.write_section(&self.volumes);
+    data.extend(&volumes);
+
+    // Header
+    write(&mut data, 0x0, 4, self.version.value() as u64);
+    data[0x4 .. 0x8].copy_from_slice(&super::constants::HEADER_CONSTANT_FIELD);
+    write(&mut data, 0x8, 4, match self.version {
+      FormatVersion::WindowsXp2003 => 0x0f,
+      _ => 0x11
+    });
+    let size = data.len() as u64;
+    write(&mut data, 0xc, 4, size);
+    for (i, unit) in self.name.units().iter()
+        .take(super::constants::MAX_FILENAME_LENGTH).enumerate() {
+      write(&mut data, 0x10 + i * 2, 2, *unit as u64);
+    }
+    write(&mut data, 0x4c, 4, self.hash as u64);
+
+    // File information
+    write(&mut data, 0x54, 4, metrics_offset as u64);
+    write(&mut data, 0x58, 4, self.metrics.len() as u64);
+    write(&mut data, 0x5c, 4, trace_offset as u64);
+    write(&mut data, 0x60, 4, self.trace.len() as u64);
+    write(&mut data, 0x64, 4, names_offset as u64);
+    write(&mut data, 0x68, 4, names_length as u64);
+    write(&mut data, 0x6c, 4, volumes_offset as u64);
+    write(&mut data, 0x70, 4, self.volumes.len() as u64);
+    write(&mut data, 0x74, 4, volumes.len() as u64);
+    let (times, slots, counter) = match self.version {
+      FormatVersion::WindowsXp2003 => (0x78, 1, 0x90),
+      FormatVersion::WindowsVista7 => (0x80, 1, 0x98),
+      _ => (0x80, 8, 0xd0)
+    };
+    for (i, time) in self.last_execution_times.iter().take(slots).enumerate() {
+      write(&mut data, times + i * 8, 8, time.value());
+    }
+    write(&mut data, counter, 4, self.execution_counter as u64);
+
+    data
+  }
+
+  /// Writes the file to a `std::io::Write` destination.
+  pub fn write<T>(&self, mut dst: T) -> super::Result<()>
+    where T: std::io::Write {
+    dst.write_all(&self.to_bytes()).map_err(super::error::Error::IOError)
+  }
+}